@@ -0,0 +1,167 @@
+use crate::ray::Ray;
+use cgmath::{ElementWise, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// A box containing nothing; growing it by any point or box yields that point or box.
+    pub fn empty() -> Self {
+        Self {
+            min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points<I>(points: I) -> Self
+    where
+        I: IntoIterator<Item = Point3<f32>>,
+    {
+        points
+            .into_iter()
+            .fold(Self::empty(), |aabb, point| aabb.grow(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn grow(&self, point: Point3<f32>) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x.min(point.x),
+                self.min.y.min(point.y),
+                self.min.z.min(point.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(point.x),
+                self.max.y.max(point.y),
+                self.max.z.max(point.z),
+            ),
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        self.grow(other.min).grow(other.max)
+    }
+
+    /// The box where `self` and `other` overlap, which is empty if they do not.
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x.max(other.min.x),
+                self.min.y.max(other.min.y),
+                self.min.z.max(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.min(other.max.x),
+                self.max.y.min(other.max.y),
+                self.max.z.min(other.max.z),
+            ),
+        }
+    }
+
+    pub fn extent(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    pub fn centroid(&self) -> Point3<f32> {
+        self.min + self.extent() * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let extent = self.extent();
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    /// Index of the axis along which the box is largest.
+    pub fn largest_axis(&self) -> usize {
+        let extent = self.extent();
+        if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// The eight corners of the box.
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Point3::new(min.x, min.y, min.z),
+            Point3::new(max.x, min.y, min.z),
+            Point3::new(min.x, max.y, min.z),
+            Point3::new(max.x, max.y, min.z),
+            Point3::new(min.x, min.y, max.z),
+            Point3::new(max.x, min.y, max.z),
+            Point3::new(min.x, max.y, max.z),
+            Point3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// Slab test. Returns the distance at which the ray enters the box, if it does so before
+    /// `max_distance`. `inverse_direction` is the component-wise reciprocal of the ray direction.
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        inverse_direction: &Vector3<f32>,
+        max_distance: f32,
+    ) -> Option<f32> {
+        let (near, far) = self.slabs(ray, inverse_direction);
+        if near <= far && far >= 0.0 && near <= max_distance {
+            Some(near.max(0.0))
+        } else {
+            None
+        }
+    }
+
+    /// Distances along the whole line of `ray`, behind its origin as well as in front of it, at
+    /// which it enters and leaves the box.
+    pub fn crossing(&self, ray: &Ray) -> Option<(f32, f32)> {
+        let (near, far) = self.slabs(ray, &ray.direction.map(|v| 1.0 / v));
+        (near <= far).then_some((near, far))
+    }
+
+    fn slabs(&self, ray: &Ray, inverse_direction: &Vector3<f32>) -> (f32, f32) {
+        let t0 = (self.min - ray.origin).mul_element_wise(*inverse_direction);
+        let t1 = (self.max - ray.origin).mul_element_wise(*inverse_direction);
+
+        let near = t0.x.min(t1.x).max(t0.y.min(t1.y)).max(t0.z.min(t1.z));
+        let far = t0.x.max(t1.x).min(t0.y.max(t1.y)).min(t0.z.max(t1.z));
+        (near, far)
+    }
+}
+
+#[test]
+pub fn ray_aabb_intersection() {
+    let aabb = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let inverse_direction = ray.direction.map(|v| 1.0 / v);
+
+    assert_eq!(
+        aabb.intersect(&ray, &inverse_direction, f32::INFINITY),
+        Some(4.0)
+    );
+    assert_eq!(aabb.intersect(&ray, &inverse_direction, 3.0), None);
+
+    let ray = Ray::new(Point3::new(2.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    assert_eq!(
+        aabb.intersect(&ray, &inverse_direction, f32::INFINITY),
+        None
+    );
+}
@@ -0,0 +1,431 @@
+use crate::{
+    aabb::Aabb,
+    hit::Hit,
+    intersectable::{Intersectable, Intersectables, Interval},
+    light::Emitter,
+    ray::Ray,
+};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use std::{collections::HashMap, sync::Arc};
+
+const NUMBER_OF_BINS: usize = 16;
+const MAX_PRIMITIVES_IN_LEAF: usize = 4;
+const TRAVERSAL_COST: f32 = 1.0;
+const INTERSECTION_COST: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy)]
+enum BvhNodeKind {
+    Leaf { first: usize, count: usize },
+    // The first child is always stored directly after its parent.
+    Interior { second_child: usize, axis: usize },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Aabb,
+    kind: BvhNodeKind,
+}
+
+/// A bounding volume hierarchy over primitives identified by their index.
+///
+/// The tree only stores indices, so it can accelerate anything that can be bounded, whether that
+/// is a list of `Intersectable`s or the triangles of a mesh.
+#[derive(Debug, Clone, Default)]
+pub struct BvhTree {
+    nodes: Vec<BvhNode>,
+    primitives: Vec<usize>,
+}
+
+struct BuildPrimitive {
+    index: usize,
+    bounds: Aabb,
+    centroid: cgmath::Point3<f32>,
+}
+
+impl BvhTree {
+    /// Builds the tree using the surface area heuristic over the given primitive bounds.
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut build_primitives: Vec<BuildPrimitive> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, bounds)| BuildPrimitive {
+                index,
+                bounds: *bounds,
+                centroid: bounds.centroid(),
+            })
+            .collect();
+
+        let mut tree = Self {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            primitives: Vec::with_capacity(bounds.len()),
+        };
+
+        if !build_primitives.is_empty() {
+            tree.build_node(&mut build_primitives);
+        }
+
+        tree
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bounds)
+    }
+
+    fn build_node(&mut self, build_primitives: &mut [BuildPrimitive]) -> usize {
+        let bounds = build_primitives
+            .iter()
+            .fold(Aabb::empty(), |bounds, primitive| {
+                bounds.union(&primitive.bounds)
+            });
+        let node_index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            kind: BvhNodeKind::Leaf { first: 0, count: 0 },
+        });
+
+        match Self::find_split(&bounds, build_primitives) {
+            Some((axis, split_position)) => {
+                let mut middle = partition(build_primitives, |primitive| {
+                    primitive.centroid[axis] < split_position
+                });
+                if middle == 0 || middle == build_primitives.len() {
+                    middle = build_primitives.len() / 2;
+                }
+
+                let (first, second) = build_primitives.split_at_mut(middle);
+                self.build_node(first);
+                let second_child = self.build_node(second);
+                self.nodes[node_index].kind = BvhNodeKind::Interior { second_child, axis };
+            }
+            None => {
+                let first = self.primitives.len();
+                self.primitives
+                    .extend(build_primitives.iter().map(|primitive| primitive.index));
+                self.nodes[node_index].kind = BvhNodeKind::Leaf {
+                    first,
+                    count: build_primitives.len(),
+                };
+            }
+        }
+
+        node_index
+    }
+
+    /// Finds the cheapest split plane according to the surface area heuristic, or `None` if
+    /// a leaf is cheaper.
+    fn find_split(bounds: &Aabb, build_primitives: &[BuildPrimitive]) -> Option<(usize, f32)> {
+        if build_primitives.len() <= MAX_PRIMITIVES_IN_LEAF {
+            return None;
+        }
+
+        let centroid_bounds = Aabb::from_points(build_primitives.iter().map(|p| p.centroid));
+        let axis = centroid_bounds.largest_axis();
+        let axis_min = centroid_bounds.min[axis];
+        let axis_extent = centroid_bounds.max[axis] - axis_min;
+        if axis_extent <= 0.0 {
+            // All centroids coincide, so no split can separate them
+            return None;
+        }
+
+        let mut bin_bounds = [Aabb::empty(); NUMBER_OF_BINS];
+        let mut bin_counts = [0usize; NUMBER_OF_BINS];
+        let bin_index = |position: f32| {
+            let bin = ((position - axis_min) / axis_extent * NUMBER_OF_BINS as f32) as usize;
+            bin.min(NUMBER_OF_BINS - 1)
+        };
+
+        for primitive in build_primitives {
+            let bin = bin_index(primitive.centroid[axis]);
+            bin_counts[bin] += 1;
+            bin_bounds[bin] = bin_bounds[bin].union(&primitive.bounds);
+        }
+
+        // Sweep from the right to get the area and count of everything right of each split
+        let mut right_areas = [0.0f32; NUMBER_OF_BINS];
+        let mut right_counts = [0usize; NUMBER_OF_BINS];
+        let mut accumulated_bounds = Aabb::empty();
+        let mut accumulated_count = 0;
+        for bin in (1..NUMBER_OF_BINS).rev() {
+            accumulated_bounds = accumulated_bounds.union(&bin_bounds[bin]);
+            accumulated_count += bin_counts[bin];
+            right_areas[bin] = accumulated_bounds.surface_area();
+            right_counts[bin] = accumulated_count;
+        }
+
+        let mut best_split = None;
+        let mut best_cost = f32::INFINITY;
+        let mut accumulated_bounds = Aabb::empty();
+        let mut accumulated_count = 0;
+        for bin in 0..NUMBER_OF_BINS - 1 {
+            accumulated_bounds = accumulated_bounds.union(&bin_bounds[bin]);
+            accumulated_count += bin_counts[bin];
+            let cost = accumulated_bounds.surface_area() * accumulated_count as f32
+                + right_areas[bin + 1] * right_counts[bin + 1] as f32;
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = Some(bin + 1);
+            }
+        }
+
+        let leaf_cost = INTERSECTION_COST * build_primitives.len() as f32;
+        let split_cost = TRAVERSAL_COST + INTERSECTION_COST * best_cost / bounds.surface_area();
+        if split_cost >= leaf_cost {
+            return None;
+        }
+
+        best_split.map(|bin| {
+            (
+                axis,
+                axis_min + axis_extent * bin as f32 / NUMBER_OF_BINS as f32,
+            )
+        })
+    }
+
+    /// Finds the closest hit along the ray. `intersect_primitive` is called with the index of
+    /// every primitive whose leaf the ray passes through.
+    pub fn intersect<F>(&self, ray: &Ray, mut intersect_primitive: F) -> Option<Hit>
+    where
+        F: FnMut(usize, &Ray) -> Option<Hit>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inverse_direction = ray.direction.map(|v| 1.0 / v);
+        let mut closest_hit: Option<Hit> = None;
+        let mut closest_distance = f32::INFINITY;
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node: &BvhNode = &self.nodes[node_index];
+            if node
+                .bounds
+                .intersect(ray, &inverse_direction, closest_distance)
+                .is_none()
+            {
+                continue;
+            }
+
+            match node.kind {
+                BvhNodeKind::Leaf { first, count } => {
+                    for &primitive in &self.primitives[first..first + count] {
+                        if let Some(hit) = intersect_primitive(primitive, ray) {
+                            if hit.distance < closest_distance {
+                                closest_distance = hit.distance;
+                                closest_hit = Some(hit);
+                            }
+                        }
+                    }
+                }
+                BvhNodeKind::Interior { second_child, axis } => {
+                    // Visit the child nearest to the ray origin first
+                    if ray.direction[axis] < 0.0 {
+                        stack.push(node_index + 1);
+                        stack.push(second_child);
+                    } else {
+                        stack.push(second_child);
+                        stack.push(node_index + 1);
+                    }
+                }
+            }
+        }
+
+        closest_hit
+    }
+}
+
+fn partition<T, F>(items: &mut [T], predicate: F) -> usize
+where
+    F: Fn(&T) -> bool,
+{
+    let mut middle = 0;
+    for index in 0..items.len() {
+        if predicate(&items[index]) {
+            items.swap(index, middle);
+            middle += 1;
+        }
+    }
+    middle
+}
+
+/// An `Intersectable` that accelerates its children with a bounding volume hierarchy.
+///
+/// It is serialised like `Intersectables`; the hierarchy is rebuilt when it is deserialised.
+#[derive(Debug, Deserialize)]
+#[serde(from = "Intersectables")]
+pub struct Bvh {
+    tree: BvhTree,
+    bounded: Vec<Box<dyn Intersectable>>,
+    // Children without a bounding box (e.g. infinite planes) are tested separately
+    unbounded: Vec<Box<dyn Intersectable>>,
+}
+
+impl Bvh {
+    pub fn new(intersectables: Vec<Box<dyn Intersectable>>) -> Self {
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = intersectables
+            .into_iter()
+            .partition(|intersectable| intersectable.bounding_box().is_some());
+        let bounds: Vec<Aabb> = bounded
+            .iter()
+            .filter_map(|intersectable| intersectable.bounding_box())
+            .collect();
+
+        Self {
+            tree: BvhTree::build(&bounds),
+            bounded,
+            unbounded,
+        }
+    }
+
+    /// Builds a hierarchy over the leaves of `root`, looking through nested aggregates such as
+    /// `Intersectables`.
+    pub fn from_root(root: Box<dyn Intersectable>) -> Self {
+        fn flatten(
+            mut intersectable: Box<dyn Intersectable>,
+            flattened: &mut Vec<Box<dyn Intersectable>>,
+        ) {
+            match intersectable.take_children() {
+                Some(children) => children
+                    .into_iter()
+                    .for_each(|child| flatten(child, flattened)),
+                None => flattened.push(intersectable),
+            }
+        }
+
+        let mut intersectables = Vec::new();
+        flatten(root, &mut intersectables);
+        Self::new(intersectables)
+    }
+}
+
+impl From<Intersectables> for Bvh {
+    fn from(intersectables: Intersectables) -> Self {
+        Self::new(intersectables.intersectables)
+    }
+}
+
+impl Serialize for Bvh {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct Children<'a>(&'a Bvh);
+
+        impl Serialize for Children<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq(self.0.bounded.iter().chain(self.0.unbounded.iter()))
+            }
+        }
+
+        let mut state = serializer.serialize_struct("Bvh", 1)?;
+        state.serialize_field("intersectables", &Children(self))?;
+        state.end()
+    }
+}
+
+#[typetag::serde]
+impl Intersectable for Bvh {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let bounded_hit = self
+            .tree
+            .intersect(ray, |index, ray| self.bounded[index].intersect(ray));
+
+        self.unbounded
+            .iter()
+            .filter_map(|i| i.intersect(ray))
+            .chain(bounded_hit)
+            .min_by(|x, y| x.distance.partial_cmp(&y.distance).unwrap())
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.unbounded.is_empty() {
+            self.tree.bounds()
+        } else {
+            None
+        }
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        // A hierarchy over a single solid, such as a definition, is that solid
+        match (self.bounded.as_slice(), self.unbounded.as_slice()) {
+            ([only], []) | ([], [only]) => only.intervals(ray),
+            _ => None,
+        }
+    }
+
+    fn take_children(&mut self) -> Option<Vec<Box<dyn Intersectable>>> {
+        self.tree = BvhTree::default();
+        let mut children = std::mem::take(&mut self.bounded);
+        children.append(&mut self.unbounded);
+        Some(children)
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        self.bounded
+            .iter()
+            .chain(self.unbounded.iter())
+            .for_each(|intersectable| intersectable.collect_emitters(emitters));
+    }
+
+    fn resolve_references(
+        &mut self,
+        definitions: &HashMap<String, Arc<dyn Intersectable>>,
+    ) -> Result<(), String> {
+        self.bounded
+            .iter_mut()
+            .chain(self.unbounded.iter_mut())
+            .try_for_each(|intersectable| intersectable.resolve_references(definitions))?;
+
+        // References are only bounded once they are resolved, so build the tree again
+        let children = self.take_children().unwrap_or_default();
+        *self = Self::new(children);
+        Ok(())
+    }
+}
+
+#[test]
+pub fn bvh_matches_linear_intersection() {
+    use crate::{colour, material::LightMaterial, sphere::Sphere};
+    use cgmath::{Point3, Vector3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::sync::Arc;
+
+    let material = Arc::new(LightMaterial {
+        colour: colour::WHITE.into(),
+    });
+    let spheres = || -> Vec<Box<dyn Intersectable>> {
+        let mut spheres: Vec<Box<dyn Intersectable>> = Vec::new();
+        for x in -5..5 {
+            for y in -5..5 {
+                for z in -5..5 {
+                    spheres.push(Box::new(Sphere {
+                        centre: Point3::new(x as f32, y as f32, z as f32),
+                        radius: 0.3,
+                        material: material.clone(),
+                    }));
+                }
+            }
+        }
+        spheres
+    };
+
+    let linear = Intersectables {
+        intersectables: spheres(),
+    };
+    let bvh = Bvh::from_root(Box::new(Intersectables {
+        intersectables: spheres(),
+    }));
+    assert!(bvh.unbounded.is_empty());
+    assert_eq!(bvh.bounded.len(), 1000);
+
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..1000 {
+        let direction = Vector3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let ray = Ray::new(Point3::new(0.1, 0.2, 10.0), direction);
+        let expected = linear.intersect(&ray).map(|hit| hit.distance);
+        let actual = bvh.intersect(&ray).map(|hit| hit.distance);
+        assert_eq!(expected, actual);
+    }
+}
@@ -1,29 +1,178 @@
 use crate::viewport::Viewport;
-use cgmath::{Basis3, Point3, Rotation, Vector3};
+use cgmath::{Basis3, InnerSpace, Point3, Rotation, Vector3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, fmt, str::FromStr};
+
+/// How the camera maps directions onto the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum Projection {
+    /// Rectilinear, like a pinhole or thin lens, keeping straight lines straight
+    #[default]
+    Perspective,
+    /// Parallel rays, with no foreshortening. The view is as wide as the camera's orthographic
+    /// width, or as the perspective view at the focus distance if that is not given.
+    Orthographic,
+    /// Equidistant fisheye, with the angle from the view direction proportional to the distance
+    /// from the centre of the image. The field of view spans the width of the image, and may be
+    /// up to 360 degrees.
+    Fisheye,
+    /// Longitude and latitude across and down the image, covering every direction; the field of
+    /// view is ignored. Images twice as wide as they are high make 360 by 180 degree panoramas.
+    Equirectangular,
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "perspective" => Ok(Projection::Perspective),
+            "orthographic" => Ok(Projection::Orthographic),
+            "fisheye" => Ok(Projection::Fisheye),
+            "equirectangular" => Ok(Projection::Equirectangular),
+            _ => Err(format!(
+                "unknown projection '{}', expected perspective, orthographic, fisheye or equirectangular",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic => "orthographic",
+            Projection::Fisheye => "fisheye",
+            Projection::Equirectangular => "equirectangular",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Thin lens model. With a zero aperture radius the camera is a perfect pinhole. Only
+/// perspective views are blurred by the lens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Lens {
+    pub aperture_radius: f32,
+    /// Distance along the view direction of the plane in perfect focus
+    pub focus_distance: f32,
+    /// Number of aperture blades, giving a polygonal aperture; fewer than three gives a circle
+    pub aperture_blades: u32,
+    /// Rotation of the polygonal aperture in degrees
+    pub aperture_rotation: f32,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Self {
+            aperture_radius: 0.0,
+            focus_distance: 1.0,
+            aperture_blades: 0,
+            aperture_rotation: 0.0,
+        }
+    }
+}
+
+impl Lens {
+    pub fn is_pinhole(&self) -> bool {
+        self.aperture_radius <= 0.0
+    }
+
+    /// Uniformly distributed point on the aperture, relative to its centre.
+    pub fn sample_aperture<R: Rng>(&self, rng: &mut R) -> (f32, f32) {
+        let (x, y) = if self.aperture_blades < 3 {
+            let radius = rng.gen::<f32>().sqrt();
+            let angle = 2.0 * PI * rng.gen::<f32>();
+            (radius * angle.cos(), radius * angle.sin())
+        } else {
+            // Pick one of the triangles fanning out from the centre, then a point inside it
+            let blade_angle = 2.0 * PI / self.aperture_blades as f32;
+            let blade = rng.gen_range(0..self.aperture_blades) as f32;
+            let rotation = self.aperture_rotation.to_radians();
+            let (first_angle, second_angle) = (
+                rotation + blade * blade_angle,
+                rotation + (blade + 1.0) * blade_angle,
+            );
+
+            let (mut u, mut v) = (rng.gen::<f32>(), rng.gen::<f32>());
+            if u + v > 1.0 {
+                u = 1.0 - u;
+                v = 1.0 - v;
+            }
+            (
+                u * first_angle.cos() + v * second_angle.cos(),
+                u * first_angle.sin() + v * second_angle.sin(),
+            )
+        };
+
+        (x * self.aperture_radius, y * self.aperture_radius)
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct Camera {
     basis: Basis3<f32>,
     fov: f32,
     origin: Point3<f32>,
+    lens: Lens,
+    projection: Projection,
+    orthographic_width: Option<f32>,
 }
 
 impl Camera {
     pub fn new(origin: Point3<f32>, forward: Vector3<f32>, up: Vector3<f32>, fov: f32) -> Camera {
-        let basis = Basis3::look_at(forward, up);
-        Camera { basis, origin, fov }
+        // `look_at` gives the rotation into camera space; its inverse has the camera axes as columns
+        let basis = Basis3::look_at(forward, up).invert();
+        Camera {
+            basis,
+            origin,
+            fov,
+            lens: Lens::default(),
+            projection: Projection::default(),
+            orthographic_width: None,
+        }
+    }
+
+    pub fn with_lens(self, lens: Lens) -> Camera {
+        Camera { lens, ..self }
+    }
+
+    pub fn lens(&self) -> Lens {
+        self.lens
+    }
+
+    pub fn with_projection(self, projection: Projection) -> Camera {
+        Camera { projection, ..self }
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn with_orthographic_width(self, orthographic_width: Option<f32>) -> Camera {
+        Camera {
+            orthographic_width,
+            ..self
+        }
+    }
+
+    pub fn orthographic_width(&self) -> Option<f32> {
+        self.orthographic_width
     }
 
     pub fn left(&self) -> Vector3<f32> {
-        self.basis.as_ref().x.clone()
+        self.basis.as_ref().x
     }
 
     pub fn up(&self) -> Vector3<f32> {
-        self.basis.as_ref().y.clone()
+        self.basis.as_ref().y
     }
 
     pub fn forward(&self) -> Vector3<f32> {
-        self.basis.as_ref().z.clone()
+        self.basis.as_ref().z
     }
 
     pub fn translate(&mut self, vector: Vector3<f32>) {
@@ -31,7 +180,19 @@ impl Camera {
     }
 
     pub fn get_viewport(&self, width: usize, height: usize) -> Viewport {
-        Viewport::new(width, height, self.basis, self.origin, self.fov)
+        let viewport = Viewport::new(
+            width,
+            height,
+            self.basis,
+            self.origin,
+            self.fov,
+            self.lens,
+            self.projection,
+        );
+        match self.orthographic_width {
+            Some(orthographic_width) => viewport.with_orthographic_width(orthographic_width),
+            None => viewport,
+        }
     }
 }
 
@@ -45,3 +206,168 @@ impl Default for Camera {
         Camera::new(origin, forward, up, fov)
     }
 }
+
+/// How the camera is described in the scene file.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CameraDescription {
+    pub position: Point3<f32>,
+    /// Point the camera is aimed at
+    pub look_at: Point3<f32>,
+    pub up: Vector3<f32>,
+    /// Horizontal field of view in degrees
+    pub fov: f32,
+    pub aperture_radius: f32,
+    /// Defaults to the distance to `look_at`
+    pub focus_distance: Option<f32>,
+    pub aperture_blades: u32,
+    pub aperture_rotation: f32,
+    pub projection: Projection,
+    /// Width of the view of an orthographic projection; defaults to the width of the
+    /// perspective view at the focus distance
+    pub orthographic_width: Option<f32>,
+}
+
+impl Default for CameraDescription {
+    fn default() -> Self {
+        Self {
+            position: Point3::new(0.0, 0.0, 5.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            up: Vector3::new(0.0, 1.0, 0.0),
+            fov: 90.0,
+            aperture_radius: 0.0,
+            focus_distance: None,
+            aperture_blades: 0,
+            aperture_rotation: 0.0,
+            projection: Projection::default(),
+            orthographic_width: None,
+        }
+    }
+}
+
+impl From<CameraDescription> for Camera {
+    fn from(description: CameraDescription) -> Self {
+        let view_vector = description.look_at - description.position;
+        let lens = Lens {
+            aperture_radius: description.aperture_radius,
+            focus_distance: description
+                .focus_distance
+                .unwrap_or_else(|| view_vector.magnitude()),
+            aperture_blades: description.aperture_blades,
+            aperture_rotation: description.aperture_rotation,
+        };
+
+        Camera::new(
+            description.position,
+            view_vector.normalize(),
+            description.up,
+            description.fov.to_radians(),
+        )
+        .with_lens(lens)
+        .with_projection(description.projection)
+        .with_orthographic_width(description.orthographic_width)
+    }
+}
+
+#[test]
+pub fn thin_lens_rays_converge_on_focus_plane() {
+    use assert_approx_eq::assert_approx_eq;
+
+    let lens = Lens {
+        aperture_radius: 0.5,
+        focus_distance: 4.0,
+        aperture_blades: 6,
+        aperture_rotation: 15.0,
+    };
+    let camera = Camera::default().with_lens(lens);
+    let viewport = camera.get_viewport(64, 32);
+
+    let focus_points: Vec<Point3<f32>> = (0..16)
+        .map(|_| {
+            let ray = viewport.ray(10.0, 20.0).unwrap();
+            let distance = 4.0 / ray.direction.dot(camera.forward());
+            ray.origin + ray.direction * distance
+        })
+        .collect();
+    for point in &focus_points {
+        assert_approx_eq!(point.x, focus_points[0].x, 1e-4);
+        assert_approx_eq!(point.y, focus_points[0].y, 1e-4);
+    }
+
+    let mut rng = rand::thread_rng();
+    for _ in 0..100 {
+        let (x, y) = lens.sample_aperture(&mut rng);
+        assert!((x * x + y * y).sqrt() <= 0.5 + 1e-6);
+    }
+}
+
+#[test]
+pub fn project_rays() {
+    use assert_approx_eq::assert_approx_eq;
+
+    let viewport = |projection: Projection, fov: f32, width: usize, height: usize| {
+        Camera::new(
+            Point3::new(0.0, 0.0, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            fov.to_radians(),
+        )
+        .with_lens(Lens {
+            focus_distance: 2.0,
+            ..Lens::default()
+        })
+        .with_projection(projection)
+        .get_viewport(width, height)
+    };
+
+    // Parallel rays across the width of the 90 degree perspective view two units away
+    let orthographic = viewport(Projection::Orthographic, 90.0, 64, 32);
+    let left = orthographic.ray(0.0, 16.0).unwrap();
+    let right = orthographic.ray(64.0, 16.0).unwrap();
+    assert_eq!(left.direction, Vector3::new(0.0, 0.0, -1.0));
+    assert_eq!(right.direction, left.direction);
+    assert_approx_eq!(right.origin.x - left.origin.x, 4.0, 1e-5);
+
+    // Or as wide as the scene file says, with the height following the shape of the image
+    let description: CameraDescription =
+        serde_json::from_str("{ \"projection\": \"Orthographic\", \"orthographic_width\": 10 }")
+            .unwrap();
+    let orthographic = Camera::from(description).get_viewport(64, 32);
+    let (top, bottom) = (
+        orthographic.ray(32.0, 0.0).unwrap(),
+        orthographic.ray(32.0, 32.0).unwrap(),
+    );
+    assert_approx_eq!(orthographic.ray(64.0, 16.0).unwrap().origin.x, 5.0, 1e-5);
+    assert_approx_eq!(top.origin.y - bottom.origin.y, 5.0, 1e-5);
+
+    // Iterating over the image gives every pixel, with none at the corners of a tall fisheye
+    // image, which see beyond straight back
+    let rays: Vec<_> = viewport(Projection::Fisheye, 180.0, 64, 160).collect();
+    assert_eq!(rays.len(), 64 * 160);
+    assert!(rays[0].is_none() && rays[64 * 160 - 1].is_none());
+    assert!(rays[80 * 64 + 32].is_some());
+
+    // A 180 degree fisheye sees straight ahead at its centre and straight sideways at its sides,
+    // but nothing beyond straight back
+    let fisheye = viewport(Projection::Fisheye, 180.0, 64, 64);
+    assert_approx_eq!(fisheye.ray(32.0, 32.0).unwrap().direction.z, -1.0, 1e-6);
+    let side = fisheye.ray(64.0, 32.0).unwrap().direction;
+    assert_approx_eq!(side.x, 1.0, 1e-5);
+    assert_approx_eq!(side.z, 0.0, 1e-5);
+    assert!(fisheye.ray(64.0, 64.0).is_some());
+    assert!(viewport(Projection::Fisheye, 180.0, 64, 160)
+        .ray(32.0, 0.0)
+        .is_none());
+
+    // A panorama looks up along its top, and behind the camera at its sides
+    let panorama = viewport(Projection::Equirectangular, 90.0, 64, 32);
+    assert_approx_eq!(panorama.ray(10.0, 0.0).unwrap().direction.y, 1.0, 1e-5);
+    assert_approx_eq!(panorama.ray(0.0, 16.0).unwrap().direction.z, 1.0, 1e-5);
+    assert_approx_eq!(panorama.ray(48.0, 16.0).unwrap().direction.x, 1.0, 1e-5);
+
+    assert_eq!(
+        "Equirectangular".parse::<Projection>(),
+        Ok(Projection::Equirectangular)
+    );
+    assert!("cylindrical".parse::<Projection>().is_err());
+}
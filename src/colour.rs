@@ -172,7 +172,7 @@ impl Sum for Colour {
 
 impl Zero for Colour {
     fn zero() -> Self {
-        BLACK.clone()
+        BLACK
     }
 
     fn is_zero(&self) -> bool {
@@ -195,6 +195,49 @@ impl VectorSpace for Colour {
     }
 }
 
+/// Converts CIE XYZ tristimulus values to linear sRGB, clipping colours outside its gamut.
+pub fn xyz_to_linear_srgb(x: f32, y: f32, z: f32) -> Colour {
+    Colour {
+        r: (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.0),
+        g: (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.0),
+        b: (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.0),
+        a: 1.0,
+    }
+}
+
+/// Colour of the light given off by a black body at `temperature` kelvin, scaled to a luminance
+/// of one.
+pub fn blackbody(temperature: f32) -> Colour {
+    // Wyman, Sloan and Shirley's piecewise Gaussian fit of the CIE 1931 colour matching functions
+    let lobe = |wavelength: f32, mean: f32, below: f32, above: f32| {
+        let width = if wavelength < mean { below } else { above };
+        (-0.5 * ((wavelength - mean) / width).powi(2)).exp()
+    };
+
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    for step in 0..=80 {
+        let wavelength = 380.0 + 5.0 * step as f32;
+        // Planck's law, up to a constant factor, with the second radiation constant in nm K
+        let radiance =
+            wavelength.powi(-5) / ((1.4388e7 / (wavelength * temperature.max(1.0))).exp() - 1.0);
+        x += radiance
+            * (1.056 * lobe(wavelength, 599.8, 37.9, 31.0)
+                + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7)
+                - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2));
+        y += radiance
+            * (0.821 * lobe(wavelength, 568.8, 46.9, 40.5)
+                + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1));
+        z += radiance
+            * (1.217 * lobe(wavelength, 437.0, 11.8, 36.0)
+                + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8));
+    }
+
+    if y <= 0.0 {
+        return BLACK;
+    }
+    xyz_to_linear_srgb(x / y, 1.0, z / y)
+}
+
 #[test]
 pub fn deserialize_colour() {
     let colour: Colour = serde_json::from_str(
@@ -213,3 +256,18 @@ pub fn deserialize_colour() {
     assert_eq!(colour.b, 0.75);
     assert_eq!(colour.a, 1.0);
 }
+
+#[test]
+pub fn blackbody_colours() {
+    use crate::tone_mapping::luminance;
+    use assert_approx_eq::assert_approx_eq;
+
+    // Candle light is orange, daylight about white and hotter stars blue
+    let candle = blackbody(1900.0);
+    assert!(candle.r > candle.g && candle.g > candle.b);
+    let daylight = blackbody(6500.0);
+    assert_approx_eq!(daylight.r, daylight.b, 0.15);
+    let star = blackbody(15000.0);
+    assert!(star.b > star.r);
+    assert_approx_eq!(luminance(&daylight), 1.0, 0.02);
+}
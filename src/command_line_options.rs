@@ -1,3 +1,4 @@
+use rusty_path_tracer::{camera::Projection, filter::Filter, tone_mapping::ToneMappingOperator};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -23,11 +24,60 @@ pub struct CommandLineOptions {
     #[structopt(default_value = "100", long)]
     pub num_chunks: usize,
 
-    ///Name of output image
+    ///Number of paths traced per pixel, at least one; more samples means less noise
+    #[structopt(default_value = "16", long, parse(try_from_str = parse_samples_per_pixel))]
+    pub samples_per_pixel: usize,
+
+    ///Pixel reconstruction filter (box, tent, gaussian or mitchell)
+    #[structopt(default_value = "box", long)]
+    pub filter: Filter,
+
+    ///Lens aperture radius, overriding the scene camera; zero gives a pinhole camera
+    #[structopt(long)]
+    pub aperture_radius: Option<f32>,
+
+    ///Distance to the plane in focus, overriding the scene camera
+    #[structopt(long)]
+    pub focus_distance: Option<f32>,
+
+    ///Number of aperture blades, overriding the scene camera; fewer than three gives a circle
+    #[structopt(long)]
+    pub aperture_blades: Option<u32>,
+
+    ///Camera projection (perspective, orthographic, fisheye or equirectangular), overriding the
+    ///scene camera
+    #[structopt(long)]
+    pub projection: Option<Projection>,
+
+    ///Tone mapping operator (clamp, reinhard, extended-reinhard or aces)
+    #[structopt(default_value = "clamp", long)]
+    pub tone_mapping: ToneMappingOperator,
+
+    ///Exposure adjustment in stops, applied before tone mapping
+    #[structopt(default_value = "0", long, allow_hyphen_values = true)]
+    pub exposure: f32,
+
+    ///Luminance mapped to white by the extended-reinhard operator
+    #[structopt(default_value = "4", long)]
+    pub white_point: f32,
+
+    ///Name of output image; the extension selects the format (png, ppm, exr or hdr)
     #[structopt(default_value = "image.ppm", long)]
     pub image_name: String,
 
+    ///Bits per channel for png images (8 or 16)
+    #[structopt(default_value = "8", long)]
+    pub bit_depth: u8,
+
     ///Run real-time UI
     #[structopt(short)]
     pub real_time_ui: bool,
 }
+
+fn parse_samples_per_pixel(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(0) => Err("at least one sample per pixel is needed".to_string()),
+        Ok(samples) => Ok(samples),
+        Err(error) => Err(error.to_string()),
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{
+    aabb::Aabb,
+    cylinder::{
+        convex_interval, default_capped, intersect_cap, side_uv, solve_quadratic, AxialFrame,
+        Crossings,
+    },
+    disk::disk_bounds,
+    hit::Hit,
+    intersectable::{Intersectable, Interval},
+    light::{Emitter, SurfaceSample},
+    material::Material,
+    ray::Ray,
+};
+use cgmath::{InnerSpace, Point3, Vector3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, sync::Arc};
+
+/// A cone narrowing from a base of `radius` at `base` to a point at `apex`, with the base closed
+/// by a disk unless `capped` is turned off.
+///
+/// UV coordinates on the side run around the axis and from the base to the apex; on the base they
+/// run from 0 to 1 across the square enclosing it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Cone {
+    pub base: Point3<f32>,
+    pub apex: Point3<f32>,
+    pub radius: f32,
+    #[serde(default = "default_capped")]
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+}
+
+impl Cone {
+    /// Every point where the line of `ray` crosses the surface, nearest first, in the frame of
+    /// the cone.
+    fn crossings(&self, ray: &Ray) -> (AxialFrame, Crossings) {
+        let frame = AxialFrame::new(self.base, self.apex);
+        let origin = frame.to_local(ray.origin - self.base);
+        let direction = frame.to_local(ray.direction);
+        let mut crossings = Crossings::new();
+
+        // The side is where x² + y² = (radius - slope * z)²
+        let slope = self.radius / frame.length;
+        let origin_radius = self.radius - slope * origin.z;
+        let a = direction.x * direction.x + direction.y * direction.y
+            - slope * slope * direction.z * direction.z;
+        let b =
+            origin.x * direction.x + origin.y * direction.y + slope * origin_radius * direction.z;
+        let c = origin.x * origin.x + origin.y * origin.y - origin_radius * origin_radius;
+        let (roots, count) = solve_quadratic(a, b, c);
+        for &distance in &roots[..count] {
+            let point = origin + direction * distance;
+            if (0.0..=frame.length).contains(&point.z) {
+                let normal =
+                    Vector3::new(point.x, point.y, slope * (self.radius - slope * point.z))
+                        .normalize();
+                crossings.push((distance, normal, side_uv(point, frame.length)));
+            }
+        }
+
+        if self.capped {
+            if let Some((distance, uv)) = intersect_cap(origin, direction, 0.0, self.radius) {
+                crossings.push((distance, -Vector3::unit_z(), uv));
+            }
+        }
+
+        crossings.sort();
+        (frame, crossings)
+    }
+}
+
+#[typetag::serde]
+impl Intersectable for Cone {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let (frame, crossings) = self.crossings(ray);
+        let crossing = crossings
+            .as_slice()
+            .iter()
+            .find(|crossing| crossing.0 >= 0.0)?;
+        Some(frame.hit(ray, *crossing, &self.material))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        if !self.capped {
+            return None;
+        }
+
+        let (frame, crossings) = self.crossings(ray);
+        Some(convex_interval(
+            ray,
+            &frame,
+            crossings.as_slice(),
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let axis = (self.apex - self.base).normalize();
+        Some(disk_bounds(self.base, axis, self.radius).grow(self.apex))
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        if self.material.is_emissive() {
+            emitters.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+impl Cone {
+    fn side_area(&self) -> f32 {
+        let length = (self.apex - self.base).magnitude();
+        PI * self.radius * (self.radius * self.radius + length * length).sqrt()
+    }
+}
+
+impl Emitter for Cone {
+    fn sample_surface(&self) -> SurfaceSample {
+        let mut rng = rand::thread_rng();
+        let frame = AxialFrame::new(self.base, self.apex);
+        let angle = 2.0 * PI * rng.gen::<f32>();
+        let around = Vector3::new(angle.cos(), angle.sin(), 0.0);
+
+        // The side and the base both have more area further from the axis, so pick the distance
+        // from the axis as for a disk
+        let radius = self.radius * rng.gen::<f32>().sqrt();
+        let (point, normal) = if rng.gen::<f32>() * self.area() < self.side_area() {
+            let slope = self.radius / frame.length;
+            let height = frame.length * (1.0 - radius / self.radius);
+            (
+                around * radius + Vector3::unit_z() * height,
+                (around + Vector3::unit_z() * slope).normalize(),
+            )
+        } else {
+            (around * radius, -Vector3::unit_z())
+        };
+
+        SurfaceSample {
+            position: self.base + frame.to_world(point),
+            normal: frame.to_world(normal),
+            pdf: 1.0 / self.area(),
+        }
+    }
+
+    fn surface_pdf(&self, _hit: &Hit) -> f32 {
+        1.0 / self.area()
+    }
+
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit> {
+        self.intersect(ray)
+    }
+
+    fn area(&self) -> f32 {
+        let base = if self.capped {
+            PI * self.radius * self.radius
+        } else {
+            0.0
+        };
+        self.side_area() + base
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        &self.material
+    }
+}
+
+#[test]
+pub fn intersect_cone() {
+    use crate::{colour, material::LightMaterial};
+    use assert_approx_eq::assert_approx_eq;
+
+    let cone = Cone {
+        base: Point3::new(0.0, 0.0, 0.0),
+        apex: Point3::new(0.0, 1.0, 0.0),
+        radius: 1.0,
+        capped: true,
+        material: Arc::new(LightMaterial {
+            colour: colour::WHITE.into(),
+        }),
+    };
+
+    // Halfway up, the cone has a radius of a half and its side slopes at 45 degrees
+    let side = cone
+        .intersect(&Ray::new(
+            Point3::new(0.0, 0.5, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        ))
+        .unwrap();
+    assert_approx_eq!(side.distance, 4.5, 1e-5);
+    assert_approx_eq!(side.normal.y, 0.5f32.sqrt(), 1e-5);
+    assert_approx_eq!(side.normal.z, 0.5f32.sqrt(), 1e-5);
+
+    let base = cone
+        .intersect(&Ray::new(
+            Point3::new(0.5, -2.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        ))
+        .unwrap();
+    assert_approx_eq!(base.distance, 2.0, 1e-5);
+    assert_approx_eq!(base.normal.y, -1.0, 1e-5);
+    assert!(base.front_face);
+
+    // Passes above the apex
+    assert!(cone
+        .intersect(&Ray::new(
+            Point3::new(0.0, 1.5, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        ))
+        .is_none());
+}
@@ -0,0 +1,184 @@
+use crate::{
+    aabb::Aabb,
+    hit::Hit,
+    intersectable::{Intersectable, Interval},
+    ray::Ray,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+
+/// How the solids of a `Csg` node are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CsgOperation {
+    /// Everything inside either solid
+    Union,
+    /// Only what is inside both solids
+    Intersection,
+    /// What is inside `left` but not inside `right`
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Constructive solid geometry: a solid made by combining two others. Both children must enclose
+/// a volume, like spheres, boxes, capped cylinders and cones, or other `Csg` nodes; anything else
+/// is treated as empty.
+///
+/// Surfaces keep the material of the child they come from, so the inside of a hole cut by
+/// `Difference` has the material of `right`.
+///
+/// Emissive materials light the scene only where paths bounce into them: the combined surface is
+/// not sampled directly as a light, so small bright parts converge slowly.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Box<dyn Intersectable>,
+    pub right: Box<dyn Intersectable>,
+}
+
+impl Csg {
+    /// Merges the sorted intervals of the children into those of the combined solid.
+    fn combine(&self, left: Vec<Interval>, right: Vec<Interval>) -> Vec<Interval> {
+        // Every boundary of either child, as (distance, is on left, is entering, hit)
+        let mut boundaries: Vec<(f32, bool, bool, Hit)> = Vec::new();
+        for (intervals, is_left) in [(left, true), (right, false)] {
+            for interval in intervals {
+                boundaries.push((interval.entry.distance, is_left, true, interval.entry));
+                boundaries.push((interval.exit.distance, is_left, false, interval.exit));
+            }
+        }
+        boundaries.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap());
+
+        let (mut in_left, mut in_right) = (false, false);
+        let mut entry: Option<Hit> = None;
+        let mut combined = Vec::new();
+        for (_, is_left, entering, hit) in boundaries {
+            if is_left {
+                in_left = entering;
+            } else {
+                in_right = entering;
+            }
+
+            // Normals already face the ray, only which side of the result it is on changes
+            let inside = self.operation.contains(in_left, in_right);
+            match entry.take() {
+                None if inside => {
+                    entry = Some(Hit {
+                        front_face: true,
+                        ..hit
+                    })
+                }
+                Some(entry_hit) if !inside => combined.push(Interval {
+                    entry: entry_hit,
+                    exit: Hit {
+                        front_face: false,
+                        ..hit
+                    },
+                }),
+                unchanged => entry = unchanged,
+            }
+        }
+
+        combined
+    }
+}
+
+#[typetag::serde]
+impl Intersectable for Csg {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        self.intervals(ray)?
+            .into_iter()
+            .flat_map(|interval| [interval.entry, interval.exit])
+            .find(|hit| hit.distance >= 0.0)
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        let left = self.left.intervals(ray).unwrap_or_default();
+        let right = self.right.intervals(ray).unwrap_or_default();
+        Some(self.combine(left, right))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let left = self.left.bounding_box();
+        let right = self.right.bounding_box();
+        match self.operation {
+            CsgOperation::Union => Some(left?.union(&right?)),
+            CsgOperation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(left.intersection(&right)),
+                (bounds, None) | (None, bounds) => bounds,
+            },
+            CsgOperation::Difference => left,
+        }
+    }
+
+    fn resolve_references(
+        &mut self,
+        definitions: &HashMap<String, Arc<dyn Intersectable>>,
+    ) -> Result<(), String> {
+        self.left.resolve_references(definitions)?;
+        self.right.resolve_references(definitions)
+    }
+}
+
+#[test]
+pub fn combine_spheres() {
+    use crate::{colour, material::LightMaterial, sphere::Sphere};
+    use assert_approx_eq::assert_approx_eq;
+    use cgmath::{Point3, Vector3};
+
+    let sphere = |x: f32| -> Box<dyn Intersectable> {
+        Box::new(Sphere {
+            centre: Point3::new(x, 0.0, 0.0),
+            radius: 1.0,
+            material: Arc::new(LightMaterial {
+                colour: colour::WHITE.into(),
+            }),
+        })
+    };
+    let along_x = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::unit_x());
+
+    // The lens where two spheres overlap runs from x = -0.5 to 0.5
+    let lens = Csg {
+        operation: CsgOperation::Intersection,
+        left: sphere(-0.5),
+        right: sphere(0.5),
+    };
+    let hit = lens.intersect(&along_x).unwrap();
+    assert_approx_eq!(hit.distance, 4.5, 1e-5);
+    assert!(hit.front_face);
+    assert_approx_eq!(lens.bounding_box().unwrap().max.x, 0.5, 1e-5);
+
+    // Taking the right sphere away leaves a crescent from x = -1.5 to -0.5
+    let crescent = Csg {
+        operation: CsgOperation::Difference,
+        left: sphere(-0.5),
+        right: sphere(0.5),
+    };
+    let intervals = crescent.intervals(&along_x).unwrap();
+    assert_eq!(intervals.len(), 1);
+    assert_approx_eq!(intervals[0].exit.distance, 4.5, 1e-5);
+
+    // From inside the crescent, the ray leaves through the surface of the hole
+    let inside = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vector3::unit_x());
+    let hit = crescent.intersect(&inside).unwrap();
+    assert_approx_eq!(hit.distance, 0.5, 1e-5);
+    assert!(!hit.front_face);
+    assert_approx_eq!(hit.normal.x, -1.0, 1e-5);
+
+    let union = Csg {
+        operation: CsgOperation::Union,
+        left: sphere(-0.5),
+        right: sphere(0.5),
+    };
+    let intervals = union.intervals(&along_x).unwrap();
+    assert_eq!(intervals.len(), 1);
+    assert_approx_eq!(intervals[0].exit.distance, 6.5, 1e-5);
+}
@@ -0,0 +1,254 @@
+use crate::{
+    aabb::Aabb,
+    hit::Hit,
+    intersectable::{Intersectable, Interval},
+    light::{Emitter, SurfaceSample},
+    material::Material,
+    ray::Ray,
+};
+use cgmath::{Deg, EuclideanSpace, Euler, One, Point3, Quaternion, Rotation, Vector2, Vector3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A box between the corners `min` and `max`, optionally rotated about its centre. It is called
+/// `Box` in scene files.
+///
+/// Each face has UV coordinates running from 0 to 1 across it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Cuboid {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+    /// Rotation about the centre of the box, in degrees about the x, y and z axes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Euler<Deg<f32>>>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Cuboid {
+    fn centre(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    fn half_extent(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    fn orientation(&self) -> Quaternion<f32> {
+        self.rotation.map_or_else(Quaternion::one, Quaternion::from)
+    }
+
+    /// Slab test along the whole line of `ray`, in the frame of the box centred on the origin.
+    fn crossing(&self, ray: &Ray) -> Option<Crossing> {
+        let inverse_orientation = self.orientation().invert();
+        let origin = inverse_orientation.rotate_vector(ray.origin - self.centre());
+        let direction = inverse_orientation.rotate_vector(ray.direction);
+        let half_extent = self.half_extent();
+
+        let mut near = (f32::NEG_INFINITY, 0);
+        let mut far = (f32::INFINITY, 0);
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / direction[axis];
+            let t0 = (-half_extent[axis] - origin[axis]) * inverse_direction;
+            let t1 = (half_extent[axis] - origin[axis]) * inverse_direction;
+            if t0.min(t1) > near.0 {
+                near = (t0.min(t1), axis);
+            }
+            if t0.max(t1) < far.0 {
+                far = (t0.max(t1), axis);
+            }
+        }
+
+        if near.0 > far.0 {
+            return None;
+        }
+
+        Some(Crossing {
+            origin,
+            direction,
+            near,
+            far,
+        })
+    }
+
+    /// Hit on the face perpendicular to `axis`, `distance` along the ray.
+    fn hit_at(&self, ray: &Ray, crossing: &Crossing, (distance, axis): (f32, usize)) -> Hit {
+        let half_extent = self.half_extent();
+        let local_point = crossing.origin + crossing.direction * distance;
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[axis] = local_point[axis].signum();
+
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let uv = Vector2::new(
+            local_point[u_axis] / (2.0 * half_extent[u_axis]) + 0.5,
+            local_point[v_axis] / (2.0 * half_extent[v_axis]) + 0.5,
+        );
+
+        let mut tangent = Vector3::new(0.0, 0.0, 0.0);
+        tangent[u_axis] = 1.0;
+
+        let orientation = self.orientation();
+        Hit::new(
+            ray,
+            distance,
+            orientation.rotate_vector(normal),
+            self.material.clone(),
+        )
+        .with_uv(uv)
+        .with_tangent(orientation.rotate_vector(tangent))
+    }
+}
+
+/// Where a ray crosses the planes of a box, in the frame of the box.
+struct Crossing {
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    /// Distance to, and axis of, the face where the ray enters
+    near: (f32, usize),
+    /// Distance to, and axis of, the face where the ray leaves
+    far: (f32, usize),
+}
+
+#[typetag::serde(name = "Box")]
+impl Intersectable for Cuboid {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let crossing = self.crossing(ray)?;
+        if crossing.far.0 < 0.0 {
+            return None;
+        }
+
+        // Use the far side if the ray starts inside the box
+        let face = if crossing.near.0 >= 0.0 {
+            crossing.near
+        } else {
+            crossing.far
+        };
+        Some(self.hit_at(ray, &crossing, face))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        Some(
+            self.crossing(ray)
+                .map(|crossing| Interval {
+                    entry: self.hit_at(ray, &crossing, crossing.near),
+                    exit: self.hit_at(ray, &crossing, crossing.far),
+                })
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let orientation = self.orientation();
+        let centre = self.centre();
+        let half_extent = self.half_extent();
+        let local = Aabb::new(
+            Point3::from_vec(-half_extent),
+            Point3::from_vec(half_extent),
+        );
+
+        Some(Aabb::from_points(local.corners().map(|corner| {
+            centre + orientation.rotate_vector(corner.to_vec())
+        })))
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        if self.material.is_emissive() {
+            emitters.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+impl Cuboid {
+    /// Area of one of the two faces perpendicular to each axis.
+    fn face_areas(&self) -> [f32; 3] {
+        let half_extent = self.half_extent();
+        [0, 1, 2].map(|axis| 4.0 * half_extent[(axis + 1) % 3] * half_extent[(axis + 2) % 3])
+    }
+}
+
+impl Emitter for Cuboid {
+    fn sample_surface(&self) -> SurfaceSample {
+        let mut rng = rand::thread_rng();
+        let face_areas = self.face_areas();
+
+        // Pick a pair of faces in proportion to their area, then one of the two
+        let mut choice = rng.gen::<f32>() * face_areas.iter().sum::<f32>();
+        let mut axis = 0;
+        while axis < 2 && choice >= face_areas[axis] {
+            choice -= face_areas[axis];
+            axis += 1;
+        }
+        let side = if rng.gen::<bool>() { 1.0 } else { -1.0 };
+
+        let half_extent = self.half_extent();
+        let mut point = Vector3::new(
+            half_extent.x * (2.0 * rng.gen::<f32>() - 1.0),
+            half_extent.y * (2.0 * rng.gen::<f32>() - 1.0),
+            half_extent.z * (2.0 * rng.gen::<f32>() - 1.0),
+        );
+        point[axis] = side * half_extent[axis];
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[axis] = side;
+
+        let orientation = self.orientation();
+        SurfaceSample {
+            position: self.centre() + orientation.rotate_vector(point),
+            normal: orientation.rotate_vector(normal),
+            pdf: 1.0 / self.area(),
+        }
+    }
+
+    fn surface_pdf(&self, _hit: &Hit) -> f32 {
+        1.0 / self.area()
+    }
+
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit> {
+        self.intersect(ray)
+    }
+
+    fn area(&self) -> f32 {
+        2.0 * self.face_areas().iter().sum::<f32>()
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        &self.material
+    }
+}
+
+#[test]
+pub fn intersect_rotated_box() {
+    use crate::{colour, material::LightMaterial};
+    use assert_approx_eq::assert_approx_eq;
+
+    let mut cuboid = Cuboid {
+        min: Point3::new(-1.0, -1.0, -1.0),
+        max: Point3::new(1.0, 1.0, 1.0),
+        rotation: None,
+        material: Arc::new(LightMaterial {
+            colour: colour::WHITE.into(),
+        }),
+    };
+
+    let ray = Ray::new(Point3::new(0.5, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let hit = cuboid.intersect(&ray).unwrap();
+    assert_eq!(hit.distance, 4.0);
+    assert_eq!(hit.normal, Vector3::new(0.0, 0.0, 1.0));
+    assert_eq!(hit.uv, Vector2::new(0.75, 0.5));
+
+    let inside = cuboid
+        .intersect(&Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::unit_x()))
+        .unwrap();
+    assert_eq!(inside.distance, 1.0);
+    assert!(!inside.front_face);
+
+    // Turned 45 degrees about y, the nearest edge is sqrt(2) from the centre
+    cuboid.rotation = Some(Euler::new(Deg(0.0), Deg(45.0), Deg(0.0)));
+    let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    assert_approx_eq!(
+        cuboid.intersect(&ray).unwrap().distance,
+        5.0 - 2f32.sqrt(),
+        1e-5
+    );
+    assert_approx_eq!(cuboid.bounding_box().unwrap().max.x, 2f32.sqrt(), 1e-5);
+}
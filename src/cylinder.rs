@@ -0,0 +1,359 @@
+use crate::{
+    aabb::Aabb,
+    disk::disk_bounds,
+    hit::Hit,
+    intersectable::{orthonormal_basis, Intersectable, Interval},
+    light::{Emitter, SurfaceSample},
+    material::Material,
+    ray::Ray,
+};
+use cgmath::{InnerSpace, Point3, Vector2, Vector3, Zero};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, sync::Arc};
+
+/// A cylinder of `radius` around the line from `start` to `end`, closed at both ends by disks
+/// unless `capped` is turned off.
+///
+/// UV coordinates on the side run around the axis and from `start` to `end`; on the caps they run
+/// from 0 to 1 across the square enclosing the cap.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Cylinder {
+    pub start: Point3<f32>,
+    pub end: Point3<f32>,
+    pub radius: f32,
+    #[serde(default = "default_capped")]
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+}
+
+pub(crate) fn default_capped() -> bool {
+    true
+}
+
+impl Cylinder {
+    /// Every point where the line of `ray` crosses the surface, nearest first, in the frame of
+    /// the cylinder.
+    fn crossings(&self, ray: &Ray) -> (AxialFrame, Crossings) {
+        let frame = AxialFrame::new(self.start, self.end);
+        let origin = frame.to_local(ray.origin - self.start);
+        let direction = frame.to_local(ray.direction);
+        let mut crossings = Crossings::new();
+
+        let a = direction.x * direction.x + direction.y * direction.y;
+        let b = origin.x * direction.x + origin.y * direction.y;
+        let c = origin.x * origin.x + origin.y * origin.y - self.radius * self.radius;
+        let (roots, count) = solve_quadratic(a, b, c);
+        for &distance in &roots[..count] {
+            let point = origin + direction * distance;
+            if (0.0..=frame.length).contains(&point.z) {
+                let normal = Vector3::new(point.x, point.y, 0.0) / self.radius;
+                crossings.push((distance, normal, side_uv(point, frame.length)));
+            }
+        }
+
+        if self.capped {
+            for (height, normal_z) in [(0.0, -1.0), (frame.length, 1.0)] {
+                if let Some((distance, uv)) = intersect_cap(origin, direction, height, self.radius)
+                {
+                    crossings.push((distance, Vector3::new(0.0, 0.0, normal_z), uv));
+                }
+            }
+        }
+
+        crossings.sort();
+        (frame, crossings)
+    }
+}
+
+#[typetag::serde]
+impl Intersectable for Cylinder {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let (frame, crossings) = self.crossings(ray);
+        let crossing = crossings
+            .as_slice()
+            .iter()
+            .find(|crossing| crossing.0 >= 0.0)?;
+        Some(frame.hit(ray, *crossing, &self.material))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        if !self.capped {
+            return None;
+        }
+
+        let (frame, crossings) = self.crossings(ray);
+        Some(convex_interval(
+            ray,
+            &frame,
+            crossings.as_slice(),
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let axis = (self.end - self.start).normalize();
+        Some(
+            disk_bounds(self.start, axis, self.radius).union(&disk_bounds(
+                self.end,
+                axis,
+                self.radius,
+            )),
+        )
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        if self.material.is_emissive() {
+            emitters.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+impl Cylinder {
+    fn side_area(&self) -> f32 {
+        2.0 * PI * self.radius * (self.end - self.start).magnitude()
+    }
+}
+
+impl Emitter for Cylinder {
+    fn sample_surface(&self) -> SurfaceSample {
+        let mut rng = rand::thread_rng();
+        let frame = AxialFrame::new(self.start, self.end);
+        let angle = 2.0 * PI * rng.gen::<f32>();
+        let around = Vector3::new(angle.cos(), angle.sin(), 0.0);
+
+        let (point, normal) = if rng.gen::<f32>() * self.area() < self.side_area() {
+            let height = frame.length * rng.gen::<f32>();
+            (around * self.radius + Vector3::unit_z() * height, around)
+        } else {
+            let (height, normal_z) = if rng.gen::<bool>() {
+                (frame.length, 1.0)
+            } else {
+                (0.0, -1.0)
+            };
+            let radius = self.radius * rng.gen::<f32>().sqrt();
+            (
+                around * radius + Vector3::unit_z() * height,
+                Vector3::new(0.0, 0.0, normal_z),
+            )
+        };
+
+        SurfaceSample {
+            position: self.start + frame.to_world(point),
+            normal: frame.to_world(normal),
+            pdf: 1.0 / self.area(),
+        }
+    }
+
+    fn surface_pdf(&self, _hit: &Hit) -> f32 {
+        1.0 / self.area()
+    }
+
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit> {
+        self.intersect(ray)
+    }
+
+    fn area(&self) -> f32 {
+        let caps = if self.capped {
+            2.0 * PI * self.radius * self.radius
+        } else {
+            0.0
+        };
+        self.side_area() + caps
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        &self.material
+    }
+}
+
+/// Frame of a shape built around the line from `start` to `end`, with z along the line and the
+/// origin at `start`.
+pub(crate) struct AxialFrame {
+    tangent: Vector3<f32>,
+    bitangent: Vector3<f32>,
+    axis: Vector3<f32>,
+    pub length: f32,
+}
+
+impl AxialFrame {
+    pub fn new(start: Point3<f32>, end: Point3<f32>) -> Self {
+        let axis = end - start;
+        let length = axis.magnitude();
+        let axis = axis / length;
+        let (tangent, bitangent) = orthonormal_basis(axis, None);
+        Self {
+            tangent,
+            bitangent,
+            axis,
+            length,
+        }
+    }
+
+    pub fn to_local(&self, vector: Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(
+            cgmath::dot(vector, self.tangent),
+            cgmath::dot(vector, self.bitangent),
+            cgmath::dot(vector, self.axis),
+        )
+    }
+
+    pub fn to_world(&self, vector: Vector3<f32>) -> Vector3<f32> {
+        self.tangent * vector.x + self.bitangent * vector.y + self.axis * vector.z
+    }
+
+    pub fn hit(
+        &self,
+        ray: &Ray,
+        (distance, normal, uv): LocalHit,
+        material: &Arc<dyn Material>,
+    ) -> Hit {
+        // Around the axis on the side, along the local x axis on the caps
+        let tangent = if normal.x == 0.0 && normal.y == 0.0 {
+            Vector3::unit_x()
+        } else {
+            Vector3::new(-normal.y, normal.x, 0.0)
+        };
+
+        Hit::new(ray, distance, self.to_world(normal), material.clone())
+            .with_uv(uv)
+            .with_tangent(self.to_world(tangent))
+    }
+}
+
+/// Real roots of `a t² + 2 b t + c`, smallest first; only the first of the two is a root if the
+/// count is one.
+pub(crate) fn solve_quadratic(a: f32, b: f32, c: f32) -> ([f32; 2], usize) {
+    if a.abs() < f32::EPSILON {
+        if b.abs() < f32::EPSILON {
+            return ([0.0; 2], 0);
+        }
+        ([-c / (2.0 * b), 0.0], 1)
+    } else {
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return ([0.0; 2], 0);
+        }
+        let root = discriminant.sqrt();
+        let (t0, t1) = ((-b - root) / a, (-b + root) / a);
+        ([t0.min(t1), t0.max(t1)], 2)
+    }
+}
+
+/// Where the line of the local ray crosses the round cap of `radius` at `height` along the axis.
+pub(crate) fn intersect_cap(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    height: f32,
+    radius: f32,
+) -> Option<(f32, Vector2<f32>)> {
+    if direction.z.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let distance = (height - origin.z) / direction.z;
+    let point = origin + direction * distance;
+    if point.x * point.x + point.y * point.y > radius * radius {
+        return None;
+    }
+
+    Some((
+        distance,
+        Vector2::new(
+            point.x / (2.0 * radius) + 0.5,
+            point.y / (2.0 * radius) + 0.5,
+        ),
+    ))
+}
+
+/// UV coordinates on the side of a shape around the axis of length `length`.
+pub(crate) fn side_uv(point: Vector3<f32>, length: f32) -> Vector2<f32> {
+    Vector2::new(point.y.atan2(point.x) / (2.0 * PI) + 0.5, point.z / length)
+}
+
+pub(crate) type LocalHit = (f32, Vector3<f32>, Vector2<f32>);
+
+/// Up to four crossings of a ray with the surface of a shape, kept on the stack since every
+/// intersection test collects them.
+pub(crate) struct Crossings {
+    hits: [LocalHit; 4],
+    count: usize,
+}
+
+impl Crossings {
+    pub fn new() -> Self {
+        Self {
+            hits: [(0.0, Vector3::zero(), Vector2::zero()); 4],
+            count: 0,
+        }
+    }
+
+    pub fn push(&mut self, hit: LocalHit) {
+        self.hits[self.count] = hit;
+        self.count += 1;
+    }
+
+    /// Sorts the crossings nearest first.
+    pub fn sort(&mut self) {
+        self.hits[..self.count].sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap());
+    }
+
+    pub fn as_slice(&self) -> &[LocalHit] {
+        &self.hits[..self.count]
+    }
+}
+
+/// The interval between the first and last of the sorted `crossings` of a convex solid.
+pub(crate) fn convex_interval(
+    ray: &Ray,
+    frame: &AxialFrame,
+    crossings: &[LocalHit],
+    material: &Arc<dyn Material>,
+) -> Vec<Interval> {
+    match (crossings.first(), crossings.last()) {
+        (Some(&entry), Some(&exit)) if crossings.len() >= 2 => vec![Interval {
+            entry: frame.hit(ray, entry, material),
+            exit: frame.hit(ray, exit, material),
+        }],
+        _ => Vec::new(),
+    }
+}
+
+#[test]
+pub fn intersect_cylinder() {
+    use crate::{colour, material::LightMaterial};
+    use assert_approx_eq::assert_approx_eq;
+
+    let mut cylinder = Cylinder {
+        start: Point3::new(0.0, 0.0, 0.0),
+        end: Point3::new(0.0, 2.0, 0.0),
+        radius: 1.0,
+        capped: true,
+        material: Arc::new(LightMaterial {
+            colour: colour::WHITE.into(),
+        }),
+    };
+
+    let side = cylinder
+        .intersect(&Ray::new(
+            Point3::new(0.0, 1.5, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        ))
+        .unwrap();
+    assert_approx_eq!(side.distance, 4.0, 1e-5);
+    assert_approx_eq!(side.normal.z, 1.0, 1e-5);
+    assert_approx_eq!(side.uv.y, 0.75, 1e-5);
+
+    let down = Ray::new(Point3::new(0.5, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+    let cap = cylinder.intersect(&down).unwrap();
+    assert_approx_eq!(cap.distance, 3.0, 1e-5);
+    assert_approx_eq!(cap.normal.y, 1.0, 1e-5);
+
+    // Without caps the ray passes straight through
+    cylinder.capped = false;
+    assert!(cylinder.intersect(&down).is_none());
+    assert_eq!(
+        cylinder.bounding_box().unwrap(),
+        Aabb::new(Point3::new(-1.0, 0.0, -1.0), Point3::new(1.0, 2.0, 1.0))
+    );
+}
@@ -0,0 +1,152 @@
+use crate::{
+    aabb::Aabb,
+    hit::Hit,
+    intersectable::{orthonormal_basis, Intersectable},
+    light::{Emitter, SurfaceSample},
+    material::Material,
+    ray::Ray,
+};
+use cgmath::{InnerSpace, Point3, Vector2, Vector3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, sync::Arc};
+
+/// A flat, round disk facing along `normal`.
+///
+/// UV coordinates run from 0 to 1 across the square enclosing the disk.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Disk {
+    pub centre: Point3<f32>,
+    pub normal: Vector3<f32>,
+    pub radius: f32,
+    pub material: Arc<dyn Material>,
+}
+
+#[typetag::serde]
+impl Intersectable for Disk {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let normal = self.normal.normalize();
+        let denominator = cgmath::dot(normal, ray.direction);
+        if denominator.abs() < f32::EPSILON {
+            // Ray is parallel to disk
+            return None;
+        }
+
+        let distance = cgmath::dot(self.centre - ray.origin, normal) / denominator;
+        if distance < 0.0 {
+            return None;
+        }
+
+        let offset = ray.origin + distance * ray.direction - self.centre;
+        if offset.magnitude2() > self.radius * self.radius {
+            return None;
+        }
+
+        let (tangent, bitangent) = orthonormal_basis(normal, None);
+        let uv = Vector2::new(
+            cgmath::dot(offset, tangent) / (2.0 * self.radius) + 0.5,
+            cgmath::dot(offset, bitangent) / (2.0 * self.radius) + 0.5,
+        );
+
+        Some(
+            Hit::new(ray, distance, normal, self.material.clone())
+                .with_uv(uv)
+                .with_tangent(tangent),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(disk_bounds(
+            self.centre,
+            self.normal.normalize(),
+            self.radius,
+        ))
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        if self.material.is_emissive() {
+            emitters.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+impl Emitter for Disk {
+    fn sample_surface(&self) -> SurfaceSample {
+        let mut rng = rand::thread_rng();
+        let normal = self.normal.normalize();
+        let (tangent, bitangent) = orthonormal_basis(normal, None);
+        let radius = self.radius * rng.gen::<f32>().sqrt();
+        let angle = 2.0 * PI * rng.gen::<f32>();
+
+        SurfaceSample {
+            position: self.centre
+                + tangent * radius * angle.cos()
+                + bitangent * radius * angle.sin(),
+            normal,
+            pdf: 1.0 / self.area(),
+        }
+    }
+
+    fn surface_pdf(&self, _hit: &Hit) -> f32 {
+        1.0 / self.area()
+    }
+
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit> {
+        self.intersect(ray)
+    }
+
+    fn area(&self) -> f32 {
+        PI * self.radius * self.radius
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        &self.material
+    }
+}
+
+/// Bounds of a disk facing along the unit vector `normal`, which spans radius times the sine of
+/// the angle between the normal and each axis.
+pub fn disk_bounds(centre: Point3<f32>, normal: Vector3<f32>, radius: f32) -> Aabb {
+    let extent = Vector3::new(
+        (1.0 - normal.x * normal.x).max(0.0).sqrt(),
+        (1.0 - normal.y * normal.y).max(0.0).sqrt(),
+        (1.0 - normal.z * normal.z).max(0.0).sqrt(),
+    ) * radius;
+    Aabb::new(centre - extent, centre + extent)
+}
+
+#[test]
+pub fn intersect_disk() {
+    use crate::{colour, material::LightMaterial};
+
+    let disk = Disk {
+        centre: Point3::new(0.0, 1.0, 0.0),
+        normal: Vector3::new(0.0, 2.0, 0.0),
+        radius: 1.0,
+        material: Arc::new(LightMaterial {
+            colour: colour::WHITE.into(),
+        }),
+    };
+
+    let hit = disk
+        .intersect(&Ray::new(
+            Point3::new(0.0, 3.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+        ))
+        .unwrap();
+    assert_eq!(hit.distance, 2.0);
+    assert_eq!(hit.normal, Vector3::new(0.0, 1.0, 0.0));
+    assert_eq!(hit.uv, Vector2::new(0.5, 0.5));
+
+    // Inside the bounding square, but outside the disk
+    assert!(disk
+        .intersect(&Ray::new(
+            Point3::new(0.9, 3.0, 0.9),
+            Vector3::new(0.0, -1.0, 0.0),
+        ))
+        .is_none());
+    assert_eq!(
+        disk.bounding_box().unwrap(),
+        Aabb::new(Point3::new(-1.0, 1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+    );
+}
@@ -0,0 +1,86 @@
+/// A piecewise constant distribution over [0, 1), split into bins of equal width.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    /// Weight of each bin
+    function: Vec<f32>,
+    /// Cumulative probability at the left edge of each bin, and 1 at the end
+    cdf: Vec<f32>,
+    /// Integral of `function` over [0, 1)
+    pub integral: f32,
+}
+
+impl Distribution1D {
+    /// Distribution in proportion to the weights `function`. All zero weights are picked evenly.
+    pub fn new(function: Vec<f32>) -> Self {
+        let count = function.len() as f32;
+        let mut cdf = Vec::with_capacity(function.len() + 1);
+        cdf.push(0.0);
+        for weight in &function {
+            cdf.push(cdf.last().unwrap() + weight / count);
+        }
+
+        let integral = *cdf.last().unwrap();
+        for (index, value) in cdf.iter_mut().enumerate() {
+            *value = if integral > 0.0 {
+                *value / integral
+            } else {
+                index as f32 / count
+            };
+        }
+
+        Self {
+            function,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Picks a bin by inverting the CDF at `u`, returning the bin and how far into it `u` falls.
+    pub fn sample(&self, u: f32) -> (usize, f32) {
+        let index = self
+            .cdf
+            .partition_point(|&value| value <= u)
+            .saturating_sub(1)
+            .min(self.function.len() - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 {
+            (u - self.cdf[index]) / width
+        } else {
+            0.0
+        };
+        (index, offset.clamp(0.0, 1.0))
+    }
+
+    /// Density over [0, 1) of the points in bin `index`.
+    pub fn pdf(&self, index: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.function[index] / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    /// Probability of picking bin `index`.
+    pub fn probability(&self, index: usize) -> f32 {
+        self.pdf(index) / self.function.len() as f32
+    }
+}
+
+#[test]
+pub fn sample_distribution() {
+    use assert_approx_eq::assert_approx_eq;
+
+    let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0]);
+    assert_approx_eq!(distribution.probability(0), 0.25, 1e-6);
+    assert_eq!(distribution.probability(1), 0.0);
+    assert_approx_eq!(distribution.pdf(2), 2.25, 1e-6);
+
+    // The first quarter of the unit interval picks the first bin, the rest the last
+    assert_eq!(distribution.sample(0.1), (0, 0.4));
+    assert_eq!(distribution.sample(0.5).0, 2);
+    assert_eq!(distribution.sample(0.999).0, 2);
+
+    let even = Distribution1D::new(vec![0.0; 4]);
+    assert_eq!(even.sample(0.6).0, 2);
+    assert_eq!(even.probability(3), 0.25);
+}
@@ -0,0 +1,290 @@
+use crate::{
+    colour::Colour,
+    distribution::Distribution1D,
+    hit::Hit,
+    light::{unlisted_light, Light, LightSample},
+    material::Material,
+    ray::Ray,
+    texture::load_image,
+    tone_mapping::luminance,
+};
+use cgmath::{Deg, InnerSpace, Matrix, Matrix3, Point3, Vector2, Vector3};
+use rand::Rng;
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+use std::{f32::consts::PI, fmt, sync::Arc};
+
+/// How an environment map is described in the scene file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EnvironmentMapDescription {
+    /// Path to an equirectangular image, usually HDR or EXR, with the horizon across the middle
+    pub file_name: String,
+    /// Turns the environment about the y axis
+    #[serde(default = "default_rotation")]
+    pub rotation: Deg<f32>,
+    /// Scales the radiance of every pixel
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+}
+
+fn default_rotation() -> Deg<f32> {
+    Deg(0.0)
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+/// Radiance arriving from every direction, looked up in an equirectangular image, that can be
+/// sampled as a light in proportion to how bright each pixel is.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    /// Radiance of each pixel, in rows from the top
+    pixels: Vec<Colour>,
+    /// Rotation from the space of the image into the world
+    to_world: Matrix3<f32>,
+    /// Distribution of the rows, weighted by the solid angle they cover
+    rows: Distribution1D,
+    /// Distribution of the pixels within each row
+    columns: Vec<Distribution1D>,
+}
+
+impl EnvironmentMap {
+    pub fn load(description: &EnvironmentMapDescription) -> Result<Self, String> {
+        let (width, height, pixels) = load_image(&description.file_name).map_err(|error| {
+            format!(
+                "failed to load environment map '{}': {}",
+                description.file_name, error
+            )
+        })?;
+        let pixels: Vec<Colour> = pixels
+            .into_iter()
+            .map(|pixel| pixel * description.intensity)
+            .collect();
+
+        // Rows towards the poles are squeezed into less solid angle, so are picked less often
+        let columns: Vec<_> = pixels
+            .chunks(width)
+            .enumerate()
+            .map(|(row, pixels)| {
+                let sin_theta = (PI * (row as f32 + 0.5) / height as f32).sin();
+                Distribution1D::new(
+                    pixels
+                        .iter()
+                        .map(|pixel| luminance(pixel).max(0.0) * sin_theta)
+                        .collect(),
+                )
+            })
+            .collect();
+        let rows = Distribution1D::new(columns.iter().map(|row| row.integral).collect());
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+            to_world: Matrix3::from_angle_y(description.rotation),
+            rows,
+            columns,
+        })
+    }
+
+    /// The pixel seen looking in `direction`, and where in the image it lies.
+    fn pixel_towards(&self, direction: &Vector3<f32>) -> (usize, usize, Vector2<f32>) {
+        let direction = self.to_world.transpose() * direction.normalize();
+        let uv = Vector2::new(
+            0.5 + direction.x.atan2(-direction.z) / (2.0 * PI),
+            direction.y.clamp(-1.0, 1.0).acos() / PI,
+        );
+        let x = ((uv.x * self.width as f32) as usize).min(self.width - 1);
+        let y = ((uv.y * self.height as f32) as usize).min(self.height - 1);
+        (x, y, uv)
+    }
+
+    /// Unit direction towards the point `uv` of the image, with `v` running down from the top.
+    fn direction(&self, uv: Vector2<f32>) -> Vector3<f32> {
+        let (phi, theta) = (2.0 * PI * (uv.x - 0.5), PI * uv.y);
+        self.to_world
+            * Vector3::new(
+                theta.sin() * phi.sin(),
+                theta.cos(),
+                -theta.sin() * phi.cos(),
+            )
+    }
+
+    /// Radiance arriving from `direction`.
+    pub fn radiance(&self, direction: &Vector3<f32>) -> Colour {
+        let (x, y, _) = self.pixel_towards(direction);
+        self.pixels[y * self.width + x]
+    }
+
+    /// Solid angle density of sampling a direction through pixel `x`, `y` at polar angle with
+    /// sine `sin_theta`.
+    fn solid_angle_pdf(&self, x: usize, y: usize, sin_theta: f32) -> f32 {
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        // The image spans 2 pi radians across and pi down
+        self.rows.pdf(y) * self.columns[y].pdf(x) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+impl fmt::Debug for EnvironmentMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvironmentMap")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl Serialize for EnvironmentMap {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom(unlisted_light(
+            "EnvironmentMap",
+            "an EnvironmentMapMaterial background",
+        )))
+    }
+}
+
+impl<'de> Deserialize<'de> for EnvironmentMap {
+    fn deserialize<D: Deserializer<'de>>(_deserializer: D) -> Result<Self, D::Error> {
+        Err(de::Error::custom(unlisted_light(
+            "EnvironmentMap",
+            "an EnvironmentMapMaterial background",
+        )))
+    }
+}
+
+#[typetag::serde]
+impl Light for EnvironmentMap {
+    fn sample(&self, _position: &Point3<f32>) -> Option<LightSample> {
+        let mut rng = rand::thread_rng();
+        let (y, offset_y) = self.rows.sample(rng.gen());
+        let (x, offset_x) = self.columns[y].sample(rng.gen());
+        let uv = Vector2::new(
+            (x as f32 + offset_x) / self.width as f32,
+            (y as f32 + offset_y) / self.height as f32,
+        );
+
+        let pdf = self.solid_angle_pdf(x, y, (PI * uv.y).sin());
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: self.direction(uv),
+            distance: f32::INFINITY,
+            radiance: self.pixels[y * self.width + x],
+            pdf,
+        })
+    }
+
+    fn pdf(&self, ray: &Ray) -> Option<(f32, f32)> {
+        let (x, y, uv) = self.pixel_towards(&ray.direction);
+        Some((f32::INFINITY, self.solid_angle_pdf(x, y, (PI * uv.y).sin())))
+    }
+
+    fn power(&self, scene_radius: f32) -> f32 {
+        // The rows integrate luminance times the sine of the polar angle across the image, each
+        // unit of which covers 2 pi squared steradians, so the sphere is 4 pi steradians of the
+        // average radiance
+        let average_radiance = 2.0 * PI * PI * self.rows.integral / (4.0 * PI);
+        PI * scene_radius * scene_radius * PI * average_radiance
+    }
+}
+
+/// A background lit by an `EnvironmentMap`. Paths are not left to find bright spots in the map,
+/// such as the sun, by chance: the map is added to the lights of the scene.
+#[derive(Deserialize)]
+#[serde(try_from = "EnvironmentMapDescription")]
+pub struct EnvironmentMapMaterial {
+    description: EnvironmentMapDescription,
+    map: Arc<EnvironmentMap>,
+}
+
+impl fmt::Debug for EnvironmentMapMaterial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvironmentMapMaterial")
+            .field("description", &self.description)
+            .field("map", &self.map)
+            .finish()
+    }
+}
+
+impl TryFrom<EnvironmentMapDescription> for EnvironmentMapMaterial {
+    type Error = String;
+
+    fn try_from(description: EnvironmentMapDescription) -> Result<Self, Self::Error> {
+        Ok(Self {
+            map: Arc::new(EnvironmentMap::load(&description)?),
+            description,
+        })
+    }
+}
+
+impl Serialize for EnvironmentMapMaterial {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.description.serialize(serializer)
+    }
+}
+
+#[typetag::serde]
+impl Material for EnvironmentMapMaterial {
+    fn emitted(&self, view_direction: &Vector3<f32>, _hit: &Hit) -> Colour {
+        self.map.radiance(view_direction)
+    }
+
+    fn background_light(&self) -> Option<Arc<dyn Light>> {
+        Some(self.map.clone())
+    }
+}
+
+#[test]
+pub fn sample_environment_map() {
+    use crate::scene::TestDirectory;
+    use assert_approx_eq::assert_approx_eq;
+    use image::{Rgb, Rgb32FImage};
+
+    // A dim sky with one bright pixel, like a sun, above the horizon
+    let directory = TestDirectory::new("environment_map");
+    let file_name = directory.join("environment.hdr");
+    Rgb32FImage::from_fn(8, 4, |x, y| {
+        if (x, y) == (5, 1) {
+            Rgb([100.0; 3])
+        } else {
+            Rgb([0.1; 3])
+        }
+    })
+    .save(&file_name)
+    .unwrap();
+
+    let material: Arc<dyn Material> = serde_json::from_str::<Box<dyn Material>>(&format!(
+        "{{ \"EnvironmentMapMaterial\": {{ \"file_name\": {:?}, \"rotation\": 90, \"intensity\": 2 }} }}",
+        file_name.to_str().unwrap()
+    ))
+    .unwrap()
+    .into();
+    assert!(serde_json::to_string(&material)
+        .unwrap()
+        .contains("\"rotation\":90.0"));
+
+    let light = material.background_light().unwrap();
+    let origin = Point3::new(0.0, 0.0, 0.0);
+    let mut bright_samples = 0;
+    for _ in 0..100 {
+        let sample = light.sample(&origin).unwrap();
+        assert!(sample.distance.is_infinite());
+
+        // Light sampling and the background agree on what lies in each direction
+        let ray = Ray::new(origin, sample.direction);
+        let (_, pdf) = light.pdf(&ray).unwrap();
+        assert_approx_eq!(pdf, sample.pdf, sample.pdf * 1e-3);
+        let hit = Hit::new(&ray, f32::INFINITY, -ray.direction, material.clone());
+        assert_eq!(material.emitted(&ray.direction, &hit).r, sample.radiance.r);
+        if sample.radiance.r == 200.0 {
+            bright_samples += 1;
+        }
+    }
+    assert!(bright_samples > 90);
+}
@@ -0,0 +1,198 @@
+use crate::distribution::Distribution1D;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+const GAUSSIAN_ALPHA: f32 = 2.0;
+const MITCHELL_B: f32 = 1.0 / 3.0;
+const MITCHELL_C: f32 = 1.0 / 3.0;
+
+/// Bins across the width of a filter in the table it is sampled from.
+const SAMPLER_BINS: usize = 64;
+
+/// Pixel reconstruction filter, weighting each sample by its offset from the pixel centre.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum Filter {
+    #[default]
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+}
+
+impl Filter {
+    /// Samples further than this from the pixel centre (in pixels, along each axis) have no
+    /// influence on the pixel.
+    pub fn radius(&self) -> f32 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0,
+        }
+    }
+
+    /// Weight of a sample at offset (`x`, `y`) from the pixel centre.
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        let radius = self.radius();
+        let x = x.abs();
+        if x > radius {
+            return 0.0;
+        }
+
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => radius - x,
+            Filter::Gaussian => {
+                // Shifted down so the filter reaches zero at its radius
+                (-GAUSSIAN_ALPHA * x * x).exp() - (-GAUSSIAN_ALPHA * radius * radius).exp()
+            }
+            Filter::Mitchell => mitchell_netravali(x),
+        }
+    }
+}
+
+/// Places samples around a pixel centre in proportion to the magnitude of a filter, so that the
+/// weights of the samples are all the same size and only differ in sign.
+///
+/// Dividing by the sum of the filter weights of uniformly placed samples goes wrong for filters
+/// with negative lobes, where that sum can come close to zero. Here every sample instead counts the
+/// same towards the average, negative where the filter is.
+#[derive(Debug, Clone)]
+pub struct FilterSampler {
+    radius: f32,
+    /// The magnitude of the filter along one axis, over [-radius, radius)
+    distribution: Distribution1D,
+    /// Whether the filter is negative in each bin of `distribution`
+    negative: Vec<bool>,
+    /// Integral of the magnitude of the filter along one axis over the integral of the filter
+    scale: f32,
+}
+
+impl FilterSampler {
+    pub fn new(filter: Filter) -> Self {
+        let radius = filter.radius();
+        let values: Vec<f32> = (0..SAMPLER_BINS)
+            .map(|bin| {
+                let x = ((bin as f32 + 0.5) / SAMPLER_BINS as f32 * 2.0 - 1.0) * radius;
+                filter.evaluate_1d(x)
+            })
+            .collect();
+        let integral: f32 = values.iter().sum();
+        let magnitude_integral: f32 = values.iter().map(|value| value.abs()).sum();
+
+        Self {
+            radius,
+            distribution: Distribution1D::new(values.iter().map(|value| value.abs()).collect()),
+            negative: values.iter().map(|&value| value < 0.0).collect(),
+            scale: magnitude_integral / integral,
+        }
+    }
+
+    /// Offset from the pixel centre for the point (`u`, `v`) of the unit square, and the weight
+    /// of a sample there. The weights average to one over the square.
+    pub fn sample(&self, u: f32, v: f32) -> (f32, f32, f32) {
+        let (x, sign_x) = self.sample_1d(u);
+        let (y, sign_y) = self.sample_1d(v);
+        (x, y, sign_x * sign_y * self.scale * self.scale)
+    }
+
+    fn sample_1d(&self, u: f32) -> (f32, f32) {
+        let (bin, offset) = self.distribution.sample(u);
+        let x = ((bin as f32 + offset) / SAMPLER_BINS as f32 * 2.0 - 1.0) * self.radius;
+        let sign = if self.negative[bin] { -1.0 } else { 1.0 };
+        (x, sign)
+    }
+}
+
+/// The Mitchell-Netravali cubic over [0, 2).
+fn mitchell_netravali(x: f32) -> f32 {
+    let (b, c) = (MITCHELL_B, MITCHELL_C);
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "box" => Ok(Filter::Box),
+            "tent" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            "mitchell" => Ok(Filter::Mitchell),
+            _ => Err(format!(
+                "unknown filter '{}', expected box, tent, gaussian or mitchell",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Filter::Box => "box",
+            Filter::Tent => "tent",
+            Filter::Gaussian => "gaussian",
+            Filter::Mitchell => "mitchell",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[test]
+pub fn evaluate_filters() {
+    use assert_approx_eq::assert_approx_eq;
+
+    assert_eq!(Filter::Box.evaluate(0.4, -0.4), 1.0);
+    assert_eq!(Filter::Box.evaluate(0.6, 0.0), 0.0);
+    assert_eq!(Filter::Tent.evaluate(0.5, 0.0), 0.5);
+    assert_eq!(Filter::Gaussian.evaluate(1.5, 0.0), 0.0);
+    assert_approx_eq!(mitchell_netravali(0.0), 8.0 / 9.0, 1e-6);
+    assert_approx_eq!(mitchell_netravali(2.0), 0.0, 1e-6);
+    // The Mitchell filter has negative lobes
+    assert!(Filter::Mitchell.evaluate(1.5, 0.0) < 0.0);
+    assert_eq!("Mitchell".parse::<Filter>(), Ok(Filter::Mitchell));
+}
+
+#[test]
+pub fn sample_filters_by_magnitude() {
+    use assert_approx_eq::assert_approx_eq;
+
+    // A box filter is sampled evenly over the pixel, with all weights one
+    let sampler = FilterSampler::new(Filter::Box);
+    assert_eq!(sampler.sample(0.5, 0.5), (0.0, 0.0, 1.0));
+    let (x, y, weight) = sampler.sample(0.0, 0.99);
+    assert_approx_eq!(x, -0.5, 1e-6);
+    assert_approx_eq!(y, 0.49, 1e-6);
+    assert_eq!(weight, 1.0);
+
+    // The negative lobes of the Mitchell filter give negative weights, and the weights of evenly
+    // spread samples average to one however few of them land in the lobes
+    let sampler = FilterSampler::new(Filter::Mitchell);
+    let (x, _, weight) = sampler.sample(0.01, 0.5);
+    assert!(x < -1.0 && weight < 0.0);
+    let count = 64;
+    let total: f32 = (0..count * count)
+        .map(|index| {
+            let u = ((index % count) as f32 + 0.5) / count as f32;
+            let v = ((index / count) as f32 + 0.5) / count as f32;
+            sampler.sample(u, v).2
+        })
+        .sum();
+    assert_approx_eq!(total / (count * count) as f32, 1.0, 1e-2);
+}
@@ -1,27 +1,112 @@
-use crate::material::Material;
-use cgmath::{Point3, Vector3};
+use crate::{intersectable::orthonormal_basis, material::Material, ray::Ray};
+use cgmath::{InnerSpace, Point3, Vector2, Vector3, Zero};
 use std::sync::Arc;
 
+/// How far off the surface secondary rays are started, to keep them from hitting the surface
+/// they leave.
+const RAY_OFFSET: f32 = 1e-4;
+
 #[derive(Clone)]
 pub struct Hit {
     pub distance: f32,
     pub position: Point3<f32>,
+    /// Position in the space of the object that was hit, before any `Transform` above it moved it
+    /// into place
+    pub object_position: Point3<f32>,
+    /// Unit surface normal for shading, always on the same side of the surface as
+    /// `geometric_normal`
     pub normal: Vector3<f32>,
+    /// Unit normal of the surface itself, always facing against the incoming ray. Differs from
+    /// `normal` where shading normals are interpolated across a face.
+    pub geometric_normal: Vector3<f32>,
+    /// Whether the ray hit the outside of the surface, i.e. is entering the object
+    pub front_face: bool,
+    /// Surface coordinates of the hit, for textures and patterns
+    pub uv: Vector2<f32>,
+    /// Unit tangent perpendicular to `normal`, pointing the way `u` increases on surfaces with UV
+    /// coordinates
+    pub tangent: Vector3<f32>,
     pub material: Arc<dyn Material>,
 }
 
 impl Hit {
+    /// Creates a hit `distance` along `ray`. `outward_normal` points out of the surface and is
+    /// flipped to face the ray if the ray comes from the inside.
     pub fn new(
+        ray: &Ray,
         distance: f32,
-        position: Point3<f32>,
-        normal: Vector3<f32>,
+        outward_normal: Vector3<f32>,
         material: Arc<dyn Material>,
     ) -> Self {
+        let front_face = cgmath::dot(ray.direction, outward_normal) < 0.0;
+        let normal = if front_face {
+            outward_normal
+        } else {
+            -outward_normal
+        };
+
+        let position = ray.origin + distance * ray.direction;
         Self {
             distance,
             position,
+            object_position: position,
             normal,
+            geometric_normal: normal,
+            front_face,
+            uv: Vector2::zero(),
+            tangent: orthonormal_basis(normal, None).0,
             material,
         }
     }
+
+    /// Shades the hit with `shading_normal`, turned to the side of the surface the ray arrives
+    /// from. Which side that is, and where secondary rays start, still follow the surface itself.
+    /// Shading normals too short to have a direction are ignored.
+    pub fn with_shading_normal(self, shading_normal: Vector3<f32>) -> Self {
+        if shading_normal.magnitude2() <= f32::EPSILON * f32::EPSILON {
+            return self;
+        }
+
+        let shading_normal = shading_normal.normalize();
+        let normal = if cgmath::dot(shading_normal, self.geometric_normal) < 0.0 {
+            -shading_normal
+        } else {
+            shading_normal
+        };
+        let tangent = self.tangent;
+        Self { normal, ..self }.with_tangent(tangent)
+    }
+
+    pub fn with_uv(self, uv: Vector2<f32>) -> Self {
+        Self { uv, ..self }
+    }
+
+    /// Sets the tangent to `tangent`, made perpendicular to the normal. Tangents along the normal
+    /// are ignored.
+    pub fn with_tangent(self, tangent: Vector3<f32>) -> Self {
+        let tangent = tangent - self.normal * cgmath::dot(tangent, self.normal);
+        if tangent.magnitude2() <= f32::EPSILON * f32::EPSILON {
+            return self;
+        }
+
+        Self {
+            tangent: tangent.normalize(),
+            ..self
+        }
+    }
+
+    /// Origin for a ray leaving the hit in `direction`, nudged off the surface on the side the
+    /// ray is leaving towards.
+    pub fn offset_position(&self, direction: &Vector3<f32>) -> Point3<f32> {
+        if cgmath::dot(*direction, self.geometric_normal) >= 0.0 {
+            self.position + self.geometric_normal * RAY_OFFSET
+        } else {
+            self.position - self.geometric_normal * RAY_OFFSET
+        }
+    }
+
+    /// Ray leaving the hit in `direction`.
+    pub fn spawn_ray(&self, direction: Vector3<f32>) -> Ray {
+        Ray::new(self.offset_position(&direction), direction)
+    }
 }
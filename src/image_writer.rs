@@ -0,0 +1,214 @@
+use crate::{colour::Colour, ppm_image, tone_mapping::ToneMapper};
+use image::{ImageBuffer, ImageFormat, Rgb};
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+#[derive(Debug)]
+pub enum ImageWriteError {
+    Io(io::Error),
+    Encoding(image::ImageError),
+    UnsupportedFormat(String),
+}
+
+impl fmt::Display for ImageWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageWriteError::Io(error) => write!(f, "failed to write image: {}", error),
+            ImageWriteError::Encoding(error) => write!(f, "failed to encode image: {}", error),
+            ImageWriteError::UnsupportedFormat(extension) => write!(
+                f,
+                "unsupported image format '{}', expected png, ppm, exr or hdr",
+                extension
+            ),
+        }
+    }
+}
+
+impl Error for ImageWriteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImageWriteError::Io(error) => Some(error),
+            ImageWriteError::Encoding(error) => Some(error),
+            ImageWriteError::UnsupportedFormat(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageWriteError {
+    fn from(error: io::Error) -> Self {
+        ImageWriteError::Io(error)
+    }
+}
+
+impl From<image::ImageError> for ImageWriteError {
+    fn from(error: image::ImageError) -> Self {
+        ImageWriteError::Encoding(error)
+    }
+}
+
+/// Writes a rendered image, given as rows of linear HDR colours from the top, to a file.
+pub trait ImageWriter {
+    fn write(
+        &self,
+        path: &Path,
+        width: usize,
+        height: usize,
+        image: &[Colour],
+    ) -> Result<(), ImageWriteError>;
+}
+
+/// Picks a writer from the extension of `path`. Low dynamic range formats are tone mapped with
+/// `tone_mapper`; `bit_depth` selects between 8 and 16 bits per channel for PNG.
+pub fn image_writer_for(
+    path: &Path,
+    tone_mapper: ToneMapper,
+    bit_depth: u8,
+) -> Result<Box<dyn ImageWriter>, ImageWriteError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "ppm" => Ok(Box::new(PpmWriter { tone_mapper })),
+        "png" => match bit_depth {
+            8 | 16 => Ok(Box::new(PngWriter {
+                tone_mapper,
+                bit_depth,
+            })),
+            _ => Err(ImageWriteError::UnsupportedFormat(format!(
+                "{}-bit png",
+                bit_depth
+            ))),
+        },
+        "exr" => Ok(Box::new(FloatWriter {
+            format: ImageFormat::OpenExr,
+        })),
+        "hdr" => Ok(Box::new(FloatWriter {
+            format: ImageFormat::Hdr,
+        })),
+        _ => Err(ImageWriteError::UnsupportedFormat(extension)),
+    }
+}
+
+/// Binary (P6) PPM.
+pub struct PpmWriter {
+    pub tone_mapper: ToneMapper,
+}
+
+impl ImageWriter for PpmWriter {
+    fn write(
+        &self,
+        path: &Path,
+        width: usize,
+        height: usize,
+        image: &[Colour],
+    ) -> Result<(), ImageWriteError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        ppm_image::write_binary_ppm_image(
+            &mut file,
+            width,
+            height,
+            image.iter().map(|pixel| self.tone_mapper.apply(*pixel)),
+        )?;
+        Ok(())
+    }
+}
+
+/// 8 or 16-bit PNG.
+pub struct PngWriter {
+    pub tone_mapper: ToneMapper,
+    pub bit_depth: u8,
+}
+
+impl ImageWriter for PngWriter {
+    fn write(
+        &self,
+        path: &Path,
+        width: usize,
+        height: usize,
+        image: &[Colour],
+    ) -> Result<(), ImageWriteError> {
+        let pixels = image.iter().map(|pixel| self.tone_mapper.apply(*pixel));
+
+        if self.bit_depth == 16 {
+            let buffer: Vec<u16> = pixels
+                .flat_map(|pixel| [pixel.r, pixel.g, pixel.b])
+                .map(|value| (value * u16::MAX as f32).round() as u16)
+                .collect();
+            image_buffer::<u16>(width, height, buffer).save_with_format(path, ImageFormat::Png)?;
+        } else {
+            let buffer: Vec<u8> = pixels
+                .flat_map(|pixel| [pixel.r, pixel.g, pixel.b])
+                .map(|value| (value * u8::MAX as f32).round() as u8)
+                .collect();
+            image_buffer::<u8>(width, height, buffer).save_with_format(path, ImageFormat::Png)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Floating point OpenEXR or Radiance HDR, keeping the unclamped linear colours.
+pub struct FloatWriter {
+    pub format: ImageFormat,
+}
+
+impl ImageWriter for FloatWriter {
+    fn write(
+        &self,
+        path: &Path,
+        width: usize,
+        height: usize,
+        image: &[Colour],
+    ) -> Result<(), ImageWriteError> {
+        let buffer: Vec<f32> = image
+            .iter()
+            .flat_map(|pixel| [pixel.r, pixel.g, pixel.b])
+            .collect();
+        image_buffer::<f32>(width, height, buffer).save_with_format(path, self.format)?;
+
+        Ok(())
+    }
+}
+
+fn image_buffer<T>(width: usize, height: usize, buffer: Vec<T>) -> ImageBuffer<Rgb<T>, Vec<T>>
+where
+    Rgb<T>: image::Pixel<Subpixel = T>,
+{
+    ImageBuffer::from_raw(width as u32, height as u32, buffer)
+        .expect("image size does not match its dimensions")
+}
+
+#[test]
+pub fn write_images_by_extension() {
+    use crate::colour::{LIGHT_BLUE, WHITE};
+    use crate::scene::TestDirectory;
+
+    let directory = TestDirectory::new("image_writer");
+    let image = vec![LIGHT_BLUE, WHITE * 8.0, WHITE, LIGHT_BLUE];
+    for extension in ["ppm", "png", "exr", "hdr"] {
+        let path = directory.join(&format!("image.{}", extension));
+        image_writer_for(&path, ToneMapper::default(), 16)
+            .unwrap()
+            .write(&path, 2, 2, &image)
+            .unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+    }
+
+    let exr = image::open(directory.join("image.exr"))
+        .unwrap()
+        .into_rgb32f();
+    assert_eq!(exr.get_pixel(1, 0).0, [8.0, 8.0, 8.0]);
+
+    assert!(matches!(
+        image_writer_for(Path::new("image.bmp"), ToneMapper::default(), 8),
+        Err(ImageWriteError::UnsupportedFormat(_))
+    ));
+}
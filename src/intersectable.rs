@@ -1,11 +1,55 @@
-use crate::{hit::Hit, material::Material, ray::Ray};
-use cgmath::{InnerSpace, Point3};
+use crate::{
+    aabb::Aabb,
+    hit::Hit,
+    light::{Emitter, SurfaceSample},
+    material::Material,
+    ray::Ray,
+};
+use cgmath::{InnerSpace, Point3, Vector2, Vector3};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, sync::Arc};
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
 #[typetag::serde]
 pub trait Intersectable: Debug + Send + Sync {
     fn intersect(&self, ray: &Ray) -> Option<Hit>;
+
+    /// Bounds of the intersectable, or `None` if it is unbounded.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Hands over the children of an aggregate, so they can be rebuilt into an acceleration
+    /// structure. Primitives have no children and return `None`.
+    fn take_children(&mut self) -> Option<Vec<Box<dyn Intersectable>>> {
+        None
+    }
+
+    /// Adds the emissive primitives of the intersectable to `emitters`, so they can be sampled
+    /// as lights.
+    fn collect_emitters(&self, _emitters: &mut Vec<Arc<dyn Emitter>>) {}
+
+    /// The intervals along the whole line of `ray`, behind its origin as well as in front of it,
+    /// that lie inside the intersectable, in order. Used to combine solids with `Csg`;
+    /// intersectables that do not enclose a volume return `None`.
+    fn intervals(&self, _ray: &Ray) -> Option<Vec<Interval>> {
+        None
+    }
+
+    /// Points every `Reference` below the intersectable at its entry in `definitions`. Fails with
+    /// the name of the first reference that is not defined.
+    fn resolve_references(
+        &mut self,
+        _definitions: &HashMap<String, Arc<dyn Intersectable>>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A stretch of a ray inside a solid, from the hit where the ray enters it to the hit where it
+/// leaves.
+#[derive(Clone)]
+pub struct Interval {
+    pub entry: Hit,
+    pub exit: Hit,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -19,16 +63,46 @@ impl Intersectable for Intersectables {
         self.intersectables
             .iter()
             .filter_map(|i| i.intersect(ray))
-            .into_iter()
             .min_by(|x, y| x.distance.partial_cmp(&y.distance).unwrap())
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.intersectables
+            .iter()
+            .try_fold(Aabb::empty(), |bounds, intersectable| {
+                Some(bounds.union(&intersectable.bounding_box()?))
+            })
+    }
+
+    fn take_children(&mut self) -> Option<Vec<Box<dyn Intersectable>>> {
+        Some(std::mem::take(&mut self.intersectables))
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        self.intersectables
+            .iter()
+            .for_each(|intersectable| intersectable.collect_emitters(emitters));
+    }
+
+    fn resolve_references(
+        &mut self,
+        definitions: &HashMap<String, Arc<dyn Intersectable>>,
+    ) -> Result<(), String> {
+        self.intersectables
+            .iter_mut()
+            .try_for_each(|intersectable| intersectable.resolve_references(definitions))
+    }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Triangle {
     pub a: Point3<f32>,
     pub b: Point3<f32>,
     pub c: Point3<f32>,
+    /// UV coordinates at `a`, `b` and `c`; without them `u` runs from `a` to `b` and `v` from `a`
+    /// to `c`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uvs: Option<[Vector2<f32>; 3]>,
     pub material: Arc<dyn Material>,
 }
 
@@ -46,7 +120,7 @@ impl Intersectable for Triangle {
         }
 
         let angle_from_normal_to_ray_direction = cgmath::dot(normal, ray.direction);
-        if angle_from_normal_to_ray_direction.abs() < std::f32::EPSILON {
+        if angle_from_normal_to_ray_direction.abs() < f32::EPSILON {
             // Ray is parallel to triangle
             return None;
         }
@@ -70,17 +144,112 @@ impl Intersectable for Triangle {
         let denominator = uv * uv - uu * vv;
         let s = (uv * wv - vv * wu) / denominator;
         let t = (uv * wu - uu * wv) / denominator;
-        if s < 0.0 || s > 1.0 || t < 0.0 || (s + t) > 1.0 {
+        if !(0.0..=1.0).contains(&s) || t < 0.0 || (s + t) > 1.0 {
             return None;
         }
 
-        Some(Hit {
-            distance: ray_distance,
-            position: intersection_point,
-            normal,
-            material: self.material.clone(),
-        })
+        let uvs = self.uvs.unwrap_or(BARYCENTRIC_UVS);
+        Some(
+            Hit::new(ray, ray_distance, normal.normalize(), self.material.clone())
+                .with_uv(interpolate_uv(uvs, s, t))
+                .with_tangent(triangle_tangent(u, v, uvs)),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::from_points([self.a, self.b, self.c]))
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        if self.material.is_emissive() {
+            emitters.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+impl Emitter for Triangle {
+    fn sample_surface(&self) -> SurfaceSample {
+        let [a, b, c] = [self.a, self.b, self.c];
+        let normal = (b - a).cross(c - a);
+        SurfaceSample {
+            position: sample_triangle(a, b, c),
+            normal: normal.normalize(),
+            pdf: 2.0 / normal.magnitude(),
+        }
+    }
+
+    fn surface_pdf(&self, _hit: &Hit) -> f32 {
+        2.0 / (self.b - self.a).cross(self.c - self.a).magnitude()
+    }
+
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit> {
+        self.intersect(ray)
+    }
+
+    fn area(&self) -> f32 {
+        0.5 * (self.b - self.a).cross(self.c - self.a).magnitude()
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        &self.material
+    }
+}
+
+/// Unit tangent and bitangent completing a right-handed frame with the unit vector `normal`. The
+/// tangent follows `reference` if given, and otherwise the world axis closest to lying in the
+/// plane of the frame.
+pub fn orthonormal_basis(
+    normal: Vector3<f32>,
+    reference: Option<Vector3<f32>>,
+) -> (Vector3<f32>, Vector3<f32>) {
+    let reference = reference.unwrap_or_else(|| {
+        if normal.x.abs() < 0.9 {
+            Vector3::unit_x()
+        } else {
+            Vector3::unit_z()
+        }
+    });
+    let tangent = (reference - normal * cgmath::dot(reference, normal)).normalize();
+    (tangent, normal.cross(tangent))
+}
+
+/// UV coordinates of the corners of a triangle without its own, which make UVs equal to the
+/// barycentric coordinates of the second and third corner.
+pub const BARYCENTRIC_UVS: [Vector2<f32>; 3] = [
+    Vector2::new(0.0, 0.0),
+    Vector2::new(1.0, 0.0),
+    Vector2::new(0.0, 1.0),
+];
+
+/// UV coordinates at barycentric coordinates `s` and `t`, the weights of the second and third
+/// corner, of a triangle whose corners have UV coordinates `uvs`.
+pub fn interpolate_uv(uvs: [Vector2<f32>; 3], s: f32, t: f32) -> Vector2<f32> {
+    uvs[0] * (1.0 - s - t) + uvs[1] * s + uvs[2] * t
+}
+
+/// Direction in which `u` increases across a triangle with edges `edge_ab` and `edge_ac`, whose
+/// corners have UV coordinates `uvs`.
+pub fn triangle_tangent(
+    edge_ab: Vector3<f32>,
+    edge_ac: Vector3<f32>,
+    uvs: [Vector2<f32>; 3],
+) -> Vector3<f32> {
+    let (uv_ab, uv_ac) = (uvs[1] - uvs[0], uvs[2] - uvs[0]);
+    let determinant = uv_ab.x * uv_ac.y - uv_ab.y * uv_ac.x;
+    if determinant.abs() < f32::EPSILON {
+        // The UV mapping is degenerate
+        return edge_ab;
     }
+
+    (edge_ab * uv_ac.y - edge_ac * uv_ab.y) / determinant
+}
+
+/// Picks a point uniformly distributed over the area of the triangle `a`, `b`, `c`.
+pub fn sample_triangle(a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> Point3<f32> {
+    let mut rng = rand::thread_rng();
+    let root = rng.gen::<f32>().sqrt();
+    let (u, v) = (1.0 - root, root * rng.gen::<f32>());
+    a + u * (b - a) + v * (c - a)
 }
 
 #[test]
@@ -0,0 +1,67 @@
+//! A small path tracer.
+//!
+//! Scenes are described as JSON (see `scene.json`), loaded with [`load_scene`] and rendered with a
+//! [`Renderer`] through a [`Camera`].
+
+pub mod aabb;
+pub mod bvh;
+pub mod camera;
+pub mod colour;
+pub mod cone;
+pub mod csg;
+pub mod cuboid;
+pub mod cylinder;
+pub mod disk;
+pub mod distribution;
+pub mod environment_map;
+pub mod filter;
+pub mod hit;
+pub mod image_writer;
+pub mod intersectable;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod microfacet;
+pub mod noise;
+pub mod plane;
+pub mod ppm_image;
+pub mod ray;
+pub mod reference;
+pub mod renderer;
+pub mod scene;
+pub mod sdf;
+pub mod sky;
+pub mod sphere;
+pub mod texture;
+pub mod tone_mapping;
+pub mod transform;
+pub mod viewport;
+
+pub use aabb::Aabb;
+pub use bvh::Bvh;
+pub use camera::Camera;
+pub use colour::Colour;
+pub use cone::Cone;
+pub use csg::Csg;
+pub use cuboid::Cuboid;
+pub use cylinder::Cylinder;
+pub use disk::Disk;
+pub use environment_map::EnvironmentMapMaterial;
+pub use filter::Filter;
+pub use hit::Hit;
+pub use intersectable::{Intersectable, Intersectables, Triangle};
+pub use light::Light;
+pub use material::Material;
+pub use mesh::Mesh;
+pub use noise::NoiseTexture;
+pub use plane::Plane;
+pub use ray::Ray;
+pub use reference::Reference;
+pub use renderer::Renderer;
+pub use scene::{load_scene, parse_scene, Scene, SceneDescription, SceneLoadError};
+pub use sdf::Sdf;
+pub use sky::SkyMaterial;
+pub use sphere::Sphere;
+pub use texture::{ColourSource, ScalarSource, Texture};
+pub use tone_mapping::{ToneMapper, ToneMappingOperator};
+pub use transform::Transform;
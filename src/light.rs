@@ -0,0 +1,448 @@
+use crate::{
+    colour::Colour, hit::Hit, intersectable::orthonormal_basis, material::Material, ray::Ray,
+    tone_mapping::luminance,
+};
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector3};
+use rand::Rng;
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+use std::{f32::consts::PI, fmt::Debug, sync::Arc};
+
+/// Light arriving at a point from a sampled direction.
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    /// Unit direction from the point towards the light
+    pub direction: Vector3<f32>,
+    /// Distance to the light; anything closer along `direction` casts a shadow
+    pub distance: f32,
+    pub radiance: Colour,
+    /// Solid angle density of sampling `direction`
+    pub pdf: f32,
+}
+
+/// Relative difference in distance within which a sampled point on an emitter and the hit found
+/// by tracing a ray to it are taken to be the same point.
+const SAMPLE_TOLERANCE: f32 = 1e-3;
+
+/// A source of light that can be sampled directly, instead of waiting for paths to hit it.
+#[typetag::serde]
+pub trait Light: Debug + Send + Sync {
+    /// Samples light arriving at `position`, or `None` if no light reaches it.
+    fn sample(&self, position: &Point3<f32>) -> Option<LightSample>;
+
+    /// Distance along `ray` to the light and the solid angle density with which `sample` would
+    /// have picked the ray direction from its origin, or `None` if the ray misses the light.
+    fn pdf(&self, ray: &Ray) -> Option<(f32, f32)>;
+
+    /// Whether the light is a point or direction that paths can never hit by chance.
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    /// Rough estimate of the luminous power the light gives off, so that bright lights can be
+    /// sampled more often than dim ones. Lights at infinity count the power falling on a disk of
+    /// `scene_radius`.
+    fn power(&self, scene_radius: f32) -> f32;
+}
+
+/// A point picked on a surface.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceSample {
+    pub position: Point3<f32>,
+    /// Unit normal pointing out of the surface
+    pub normal: Vector3<f32>,
+    /// Density of picking `position`, per unit area
+    pub pdf: f32,
+}
+
+/// A primitive with an emissive material, whose surface can be sampled by an `AreaLight`.
+pub trait Emitter: Debug + Send + Sync {
+    /// Picks a point on the surface.
+    fn sample_surface(&self) -> SurfaceSample;
+
+    /// Density per unit area with which `sample_surface` picks the point of `hit`, a hit returned
+    /// by `intersect_surface`.
+    fn surface_pdf(&self, hit: &Hit) -> f32;
+
+    /// Where `ray` first hits the surface.
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit>;
+
+    /// Total area of the surface.
+    fn area(&self) -> f32;
+
+    fn material(&self) -> &Arc<dyn Material>;
+}
+
+/// Light emitted by the surface of a primitive.
+#[derive(Debug)]
+pub struct AreaLight {
+    pub emitter: Arc<dyn Emitter>,
+}
+
+impl Serialize for AreaLight {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom(unlisted_light(
+            "AreaLight",
+            "a primitive with an emissive material",
+        )))
+    }
+}
+
+impl<'de> Deserialize<'de> for AreaLight {
+    fn deserialize<D: Deserializer<'de>>(_deserializer: D) -> Result<Self, D::Error> {
+        Err(de::Error::custom(unlisted_light(
+            "AreaLight",
+            "a primitive with an emissive material",
+        )))
+    }
+}
+
+#[typetag::serde]
+impl Light for AreaLight {
+    fn sample(&self, position: &Point3<f32>) -> Option<LightSample> {
+        let surface = self.emitter.sample_surface();
+        let to_light = surface.position - position;
+        let distance = to_light.magnitude();
+        if distance <= 0.0 {
+            return None;
+        }
+
+        let ray = Ray::new(*position, to_light);
+        let cos_light = cgmath::dot(surface.normal, ray.direction).abs();
+        if cos_light <= 0.0 {
+            return None;
+        }
+
+        // Finding the sampled point as a path would gives the UV coordinates and side of the
+        // surface that emission may depend on. Points hidden behind another part of the surface
+        // are in its shadow anyway. The search starts a little way along, so that a position on
+        // the emitter itself does not find the surface it is on.
+        let offset = 0.5 * SAMPLE_TOLERANCE * distance;
+        let hit = self
+            .emitter
+            .intersect_surface(&Ray::new(*position + ray.direction * offset, ray.direction))
+            .filter(|hit| {
+                (hit.distance + offset - distance).abs() <= SAMPLE_TOLERANCE * distance
+            })?;
+        Some(LightSample {
+            direction: ray.direction,
+            distance,
+            radiance: hit.material.emitted(&ray.direction, &hit),
+            pdf: area_to_solid_angle(surface.pdf, distance, cos_light),
+        })
+    }
+
+    fn pdf(&self, ray: &Ray) -> Option<(f32, f32)> {
+        let hit = self.emitter.intersect_surface(ray)?;
+        let cos_light = cgmath::dot(hit.normal, ray.direction).abs();
+        let pdf = area_to_solid_angle(self.emitter.surface_pdf(&hit), hit.distance, cos_light);
+        Some((hit.distance, pdf))
+    }
+
+    fn power(&self, _scene_radius: f32) -> f32 {
+        // Exitance of a surface that looks equally bright from everywhere is π times its radiance
+        let emitted = luminance(&self.emitter.material().average_emission());
+        PI * emitted.max(0.0) * self.emitter.area()
+    }
+}
+
+/// Light shining equally in all directions from a single point.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PointLight {
+    pub position: Point3<f32>,
+    /// Radiant intensity, the power per unit solid angle
+    pub intensity: Colour,
+}
+
+#[typetag::serde]
+impl Light for PointLight {
+    fn sample(&self, position: &Point3<f32>) -> Option<LightSample> {
+        sample_point(&self.position, self.intensity, position)
+    }
+
+    fn pdf(&self, _ray: &Ray) -> Option<(f32, f32)> {
+        None
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn power(&self, _scene_radius: f32) -> f32 {
+        4.0 * PI * luminance(&self.intensity)
+    }
+}
+
+/// Light shining from a point into a cone, fading out smoothly between `inner_angle` and
+/// `outer_angle` from its axis.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpotLight {
+    pub position: Point3<f32>,
+    /// Direction the spot points in
+    pub direction: Vector3<f32>,
+    /// Radiant intensity along the axis, the power per unit solid angle
+    pub intensity: Colour,
+    /// Angle from the axis out to which the spot shines at full intensity
+    #[serde(default = "default_inner_angle")]
+    pub inner_angle: Deg<f32>,
+    /// Angle from the axis beyond which the spot is dark
+    #[serde(default = "default_outer_angle")]
+    pub outer_angle: Deg<f32>,
+}
+
+fn default_inner_angle() -> Deg<f32> {
+    Deg(20.0)
+}
+
+fn default_outer_angle() -> Deg<f32> {
+    Deg(30.0)
+}
+
+impl SpotLight {
+    /// Fraction of the full intensity shining in `direction`, a unit vector.
+    fn falloff(&self, direction: Vector3<f32>) -> f32 {
+        let cos_angle = cgmath::dot(direction, self.direction.normalize());
+        let cos_outer = Rad::from(self.outer_angle).0.cos();
+        let cos_inner = Rad::from(self.inner_angle).0.cos().max(cos_outer);
+        if cos_angle <= cos_outer {
+            0.0
+        } else if cos_angle >= cos_inner {
+            1.0
+        } else {
+            let t = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+#[typetag::serde]
+impl Light for SpotLight {
+    fn sample(&self, position: &Point3<f32>) -> Option<LightSample> {
+        let falloff = self.falloff((position - self.position).normalize());
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        sample_point(&self.position, self.intensity * falloff, position)
+    }
+
+    fn pdf(&self, _ray: &Ray) -> Option<(f32, f32)> {
+        None
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn power(&self, _scene_radius: f32) -> f32 {
+        // As if the spot shone at full intensity halfway into its falloff
+        let cos_half_angle = Rad::from((self.inner_angle + self.outer_angle) * 0.5)
+            .0
+            .cos();
+        2.0 * PI * (1.0 - cos_half_angle) * luminance(&self.intensity)
+    }
+}
+
+/// Light from a point at `light_position` with `intensity` towards it, arriving at `position`.
+fn sample_point(
+    light_position: &Point3<f32>,
+    intensity: Colour,
+    position: &Point3<f32>,
+) -> Option<LightSample> {
+    let to_light = light_position - position;
+    let distance = to_light.magnitude();
+    if distance <= 0.0 {
+        return None;
+    }
+
+    Some(LightSample {
+        direction: to_light / distance,
+        distance,
+        radiance: intensity * (1.0 / (distance * distance)),
+        pdf: 1.0,
+    })
+}
+
+/// Light arriving from a single direction from infinitely far away, such as sunlight. A nonzero
+/// `angular_radius` spreads the directions over a cone, which softens shadows.
+///
+/// Like point lights, directional lights cannot be seen, only the surfaces they light.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DirectionalLight {
+    /// Direction the light travels in
+    pub direction: Vector3<f32>,
+    /// Irradiance on a surface facing the light
+    pub irradiance: Colour,
+    #[serde(default = "default_angular_radius")]
+    pub angular_radius: Deg<f32>,
+}
+
+fn default_angular_radius() -> Deg<f32> {
+    Deg(0.0)
+}
+
+#[typetag::serde]
+impl Light for DirectionalLight {
+    fn sample(&self, _position: &Point3<f32>) -> Option<LightSample> {
+        let towards_light = -self.direction.normalize();
+        let cos_radius = Rad::from(self.angular_radius).0.cos();
+
+        // Sampling the cone evenly makes every direction carry an equal share of the irradiance
+        let mut rng = rand::thread_rng();
+        let cos_theta = 1.0 - rng.gen::<f32>() * (1.0 - cos_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f32>();
+        let (tangent, bitangent) = orthonormal_basis(towards_light, None);
+        let direction = tangent * (sin_theta * phi.cos())
+            + bitangent * (sin_theta * phi.sin())
+            + towards_light * cos_theta;
+
+        Some(LightSample {
+            direction: direction.normalize(),
+            distance: f32::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, _ray: &Ray) -> Option<(f32, f32)> {
+        None
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn power(&self, scene_radius: f32) -> f32 {
+        PI * scene_radius * scene_radius * luminance(&self.irradiance)
+    }
+}
+
+/// Error for a light that the scene makes for itself out of `source`, which is what the scene file
+/// describes instead, so that it cannot be listed among the lights.
+pub(crate) fn unlisted_light(name: &str, source: &str) -> String {
+    format!(
+        "{} cannot be listed among the lights of a scene; it comes from {}",
+        name, source
+    )
+}
+
+/// Converts a density per unit area into one per unit solid angle, as seen from `distance` away
+/// at an angle with cosine `cos_surface` to the surface normal.
+pub fn area_to_solid_angle(pdf: f32, distance: f32, cos_surface: f32) -> f32 {
+    if cos_surface <= 0.0 {
+        0.0
+    } else {
+        pdf * distance * distance / cos_surface
+    }
+}
+
+/// Weight of a sample drawn with density `pdf` when it could also have been drawn with density
+/// `other_pdf`, for combining the two strategies by multiple importance sampling.
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (pdf_squared, other_pdf_squared) = (pdf * pdf, other_pdf * other_pdf);
+    if pdf_squared + other_pdf_squared <= 0.0 {
+        0.0
+    } else {
+        pdf_squared / (pdf_squared + other_pdf_squared)
+    }
+}
+
+#[test]
+pub fn sample_area_light() {
+    use crate::{colour::WHITE, material::LightMaterial, sphere::Sphere};
+    use assert_approx_eq::assert_approx_eq;
+
+    let light = AreaLight {
+        emitter: Arc::new(Sphere {
+            centre: Point3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: Arc::new(LightMaterial {
+                colour: WHITE.into(),
+            }),
+        }),
+    };
+
+    // Points on the far side of the sphere are hidden by the near side
+    let position = Point3::new(0.0, 0.0, 10.0);
+    let samples: Vec<_> = (0..64).filter_map(|_| light.sample(&position)).collect();
+    assert!((1..64).contains(&samples.len()));
+    for sample in samples {
+        assert!(sample.direction.z < 0.0);
+        assert!(sample.distance <= 10.0);
+        assert_eq!(sample.radiance.r, 1.0);
+    }
+
+    // Uniform area sampling seen head on: 1 / (4 pi) per unit area, at distance 9
+    let (distance, pdf) = light
+        .pdf(&Ray::new(position, Vector3::new(0.0, 0.0, -1.0)))
+        .unwrap();
+    assert_approx_eq!(distance, 9.0, 1e-5);
+    assert_approx_eq!(pdf, 81.0 / (4.0 * std::f32::consts::PI), 1e-3);
+    assert_approx_eq!(power_heuristic(1.0, 1.0), 0.5, 1e-6);
+
+    // Exitance π over an area of 4π, the same every time
+    let power = light.power(1.0);
+    assert_approx_eq!(power, 4.0 * PI * PI, 1e-4);
+    assert_eq!(light.power(1.0), power);
+}
+
+#[test]
+pub fn sample_delta_lights() {
+    use crate::colour::WHITE;
+    use assert_approx_eq::assert_approx_eq;
+
+    let lights: Vec<Box<dyn Light>> = serde_json::from_str(
+        "[
+            { \"PointLight\": {
+                \"position\": { \"x\": 0, \"y\": 2, \"z\": 0 },
+                \"intensity\": { \"r\": 4, \"g\": 4, \"b\": 4, \"a\": 1 }
+            } },
+            { \"SpotLight\": {
+                \"position\": { \"x\": 0, \"y\": 2, \"z\": 0 },
+                \"direction\": { \"x\": 0, \"y\": -1, \"z\": 0 },
+                \"intensity\": { \"r\": 4, \"g\": 4, \"b\": 4, \"a\": 1 }
+            } },
+            { \"DirectionalLight\": {
+                \"direction\": { \"x\": 0, \"y\": -1, \"z\": 0 },
+                \"irradiance\": { \"r\": 1, \"g\": 1, \"b\": 1, \"a\": 1 },
+                \"angular_radius\": 5
+            } }
+        ]",
+    )
+    .unwrap();
+    let lights: Vec<Arc<dyn Light>> = lights.into_iter().map(Arc::from).collect();
+    assert!(lights.iter().all(|light| light.is_delta()));
+
+    // Intensity falls off with the square of the distance
+    let origin = Point3::new(0.0, 0.0, 0.0);
+    let sample = lights[0].sample(&origin).unwrap();
+    assert_approx_eq!(sample.distance, 2.0, 1e-6);
+    assert_approx_eq!(sample.radiance.r, 1.0, 1e-6);
+    assert!(lights[0]
+        .pdf(&Ray::new(origin, Vector3::unit_y()))
+        .is_none());
+
+    // The spot lights straight down at full strength, and nothing off to the side
+    assert_approx_eq!(lights[1].sample(&origin).unwrap().radiance.r, 1.0, 1e-6);
+    assert!(lights[1].sample(&Point3::new(10.0, 0.0, 0.0)).is_none());
+    let spot = SpotLight {
+        position: Point3::new(0.0, 0.0, 0.0),
+        direction: Vector3::unit_z(),
+        intensity: WHITE,
+        inner_angle: Deg(10.0),
+        outer_angle: Deg(20.0),
+    };
+    let edge = Vector3::new(15f32.to_radians().sin(), 0.0, 15f32.to_radians().cos());
+    assert!((0.0..1.0).contains(&spot.falloff(edge)));
+
+    // Sunlight arrives from within its disk, from infinitely far away
+    for _ in 0..16 {
+        let sample = lights[2].sample(&origin).unwrap();
+        assert!(sample.distance.is_infinite());
+        assert!(sample.direction.y >= 5f32.to_radians().cos() - 1e-6);
+    }
+
+    // Emissive primitives make their own lights
+    let error = serde_json::from_str::<Box<dyn Light>>("{ \"AreaLight\": {} }").unwrap_err();
+    assert!(error.to_string().contains("emissive material"));
+}
@@ -1,30 +1,11 @@
-mod camera;
-mod colour;
 mod command_line_options;
-mod hit;
-mod intersectable;
-mod material;
-mod ppm_image;
-mod ray;
-mod renderer;
-mod scene;
-mod sphere;
-mod viewport;
-
-use crate::renderer::Renderer;
-use camera::Camera;
 
 use command_line_options::CommandLineOptions;
-use intersectable::Intersectable;
-use material::*;
-
-use scene::Scene;
-
+use rusty_path_tracer::{camera::Lens, image_writer, load_scene, Camera, Renderer, ToneMapper};
 use sdl2::keyboard::{Keycode, Scancode};
 use sdl2::{event::Event, pixels::PixelFormatEnum};
-use std::fs::File;
-use std::io::Write;
-use std::{fs, time::Instant};
+use std::path::Path;
+use std::time::Instant;
 use structopt::StructOpt;
 
 // Features:
@@ -40,47 +21,61 @@ use structopt::StructOpt;
 // [X] Parallel rendering
 //   [X] Use bigger jobs?
 // [X] Realtime UI
-// [ ] Add plane primitive
-// [ ] Add mesh primitive
-// [ ] Implement refraction
-// [ ] Add sub-pixel rays
-// [ ] Support linear -> sRGB colour space (http://chilliant.blogspot.com.au/2012/08/srgb-approximations-for-hlsl.html)
-// [ ] Convert to library
+// [X] Add plane primitive
+// [X] Add mesh primitive
+// [X] Implement refraction
+// [X] Add sub-pixel rays
+// [X] Support linear -> sRGB colour space (http://chilliant.blogspot.com.au/2012/08/srgb-approximations-for-hlsl.html)
+// [X] Convert to library
 // [ ] Run firegraph to see bottle-necks
 
-pub fn load_scene(file_name: String) -> Scene {
-    let file = fs::read_to_string(file_name).expect("Failed to read scene");
-    let root: Box<dyn Intersectable> =
-        serde_json::from_str(file.as_str()).expect("Failed to parse scene");
-    let material_skybox = SkyBoxMaterial {
-        colour_top: colour::LIGHT_BLUE,
-        colour_bottom: colour::WHITE,
-    };
-
-    Scene::new(5, root, Box::new(material_skybox.clone()))
-}
-
 pub fn main() {
     let command_line_options = CommandLineOptions::from_args();
     let window_width = command_line_options.width;
     let window_height = command_line_options.height;
 
-    let scene = load_scene(command_line_options.scene);
-    let camera = Camera::default();
+    let scene = load_scene(command_line_options.scene).expect("Failed to load scene");
+    let camera = scene.camera();
+    let lens = camera.lens();
+    let camera = camera.with_lens(Lens {
+        aperture_radius: command_line_options
+            .aperture_radius
+            .unwrap_or(lens.aperture_radius),
+        focus_distance: command_line_options
+            .focus_distance
+            .unwrap_or(lens.focus_distance),
+        aperture_blades: command_line_options
+            .aperture_blades
+            .unwrap_or(lens.aperture_blades),
+        ..lens
+    });
+    let camera = match command_line_options.projection {
+        Some(projection) => camera.with_projection(projection),
+        None => camera,
+    };
     let renderer = Renderer {
         num_workers: command_line_options.num_workers,
         num_chunks: command_line_options.num_chunks,
+        samples_per_pixel: command_line_options.samples_per_pixel,
+        filter: command_line_options.filter,
         scene,
     };
+    let tone_mapper = ToneMapper {
+        operator: command_line_options.tone_mapping,
+        exposure: command_line_options.exposure,
+        white_point: command_line_options.white_point,
+    };
 
     if command_line_options.real_time_ui {
-        real_time_ui(window_width, window_height, camera, renderer);
+        real_time_ui(window_width, window_height, camera, renderer, tone_mapper);
     } else {
         render_image_to_file(
             renderer,
             camera,
+            tone_mapper,
             window_width,
             window_height,
+            command_line_options.bit_depth,
             command_line_options.image_name,
         );
     }
@@ -89,26 +84,35 @@ pub fn main() {
 fn render_image_to_file(
     renderer: Renderer,
     camera: Camera,
+    tone_mapper: ToneMapper,
     width: usize,
     height: usize,
+    bit_depth: u8,
     image_name: String,
 ) {
+    let image_path = Path::new(&image_name);
+    let image_writer = image_writer::image_writer_for(image_path, tone_mapper, bit_depth)
+        .expect("failed to choose image format");
+
     let image = renderer.render(&camera, width, height);
     let now = Instant::now();
 
     println!("Writing image... ({}ms)", now.elapsed().as_millis());
 
-    let image_string = ppm_image::write_ppm_image(width, height, image);
+    image_writer
+        .write(image_path, width, height, &image)
+        .expect("failed to write image to file");
 
     println!("Done... ({}ms)", now.elapsed().as_millis());
-
-    if let Ok(mut file) = File::create(image_name) {
-        file.write_all(image_string.as_ref())
-            .expect("failed to write image to file");
-    }
 }
 
-fn real_time_ui(window_width: usize, window_height: usize, mut camera: Camera, renderer: Renderer) {
+fn real_time_ui(
+    window_width: usize,
+    window_height: usize,
+    mut camera: Camera,
+    renderer: Renderer,
+    tone_mapper: ToneMapper,
+) {
     let sdl_context = sdl2::init().expect("failed to initialise the sdl context");
     let video_subsystem = sdl_context
         .video()
@@ -182,10 +186,10 @@ fn real_time_ui(window_width: usize, window_height: usize, mut camera: Camera, r
         texture
             .with_lock(None, |pixels, _row_size| {
                 let image = renderer.render(&camera, window_width, window_height);
-                for (i, pixel) in image.iter().enumerate() {
-                    pixels[i * 3 + 0] = (pixel.r * 255.0) as u8;
-                    pixels[i * 3 + 1] = (pixel.g * 255.0) as u8;
-                    pixels[i * 3 + 2] = (pixel.b * 255.0) as u8;
+                for (i, pixel) in tone_mapper.apply_to_image(&image).iter().enumerate() {
+                    pixels[i * 3] = (pixel.r * 255.0).round() as u8;
+                    pixels[i * 3 + 1] = (pixel.g * 255.0).round() as u8;
+                    pixels[i * 3 + 2] = (pixel.b * 255.0).round() as u8;
                 }
             })
             .expect("failed to acquire texture lock");
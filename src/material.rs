@@ -1,84 +1,167 @@
 use std::fmt::Debug;
 
 use crate::colour;
-use crate::colour::Colour;
-use crate::ray::Ray;
-use crate::scene::Scene;
+use crate::colour::{blackbody, Colour, BLACK, WHITE};
+use crate::hit::Hit;
+use crate::light::Light;
+use crate::microfacet::{fresnel_conductor, Ggx, ShadingFrame};
+use crate::texture::{ColourSource, ScalarSource};
+use crate::tone_mapping::luminance;
 use cgmath::InnerSpace;
 use cgmath::Point3;
 use cgmath::Vector3;
 use cgmath::VectorSpace;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// A direction sampled by a material to continue a path in.
+#[derive(Debug, Clone, Copy)]
+pub struct Scatter {
+    pub direction: Vector3<f32>,
+    /// Throughput of the bounce: the BSDF times the cosine term, divided by the probability
+    /// density of sampling `direction`
+    pub weight: Colour,
+    /// Solid angle density of sampling `direction`, or `None` for perfectly specular scattering,
+    /// which light sampling cannot contribute to
+    pub pdf: Option<f32>,
+}
 
 #[typetag::serde]
 pub trait Material: Debug + Sync + Send {
-    fn get_colour(
+    /// Light emitted from the hit back along `view_direction`, the direction of the incoming ray.
+    fn emitted(&self, _view_direction: &Vector3<f32>, _hit: &Hit) -> Colour {
+        BLACK
+    }
+
+    /// Samples a direction for the path to continue in, or `None` if the path ends here.
+    fn scatter(&self, _view_direction: &Vector3<f32>, _hit: &Hit) -> Option<Scatter> {
+        None
+    }
+
+    /// The BSDF times the cosine term, for light arriving from `direction` and leaving back along
+    /// `view_direction`. Perfectly specular materials return black.
+    fn bsdf(
         &self,
-        scene: &Scene,
-        view_direction: &Vector3<f32>,
-        position: &Point3<f32>,
-        normal: &Vector3<f32>,
-        ray_depth: u8,
-    ) -> Colour;
+        _view_direction: &Vector3<f32>,
+        _hit: &Hit,
+        _direction: &Vector3<f32>,
+    ) -> Colour {
+        BLACK
+    }
+
+    /// Solid angle density with which `scatter` samples `direction`.
+    fn pdf(&self, _view_direction: &Vector3<f32>, _hit: &Hit, _direction: &Vector3<f32>) -> f32 {
+        0.0
+    }
+
+    /// Whether surfaces with the material give off light, making them worth sampling as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Rough average of the light emitted from the front of surfaces with the material, for how
+    /// bright they are as lights overall.
+    fn average_emission(&self) -> Colour {
+        BLACK
+    }
+
+    /// Light that samples the material directly when it is the background of a scene, for
+    /// backgrounds too uneven to be found by chance.
+    fn background_light(&self) -> Option<Arc<dyn Light>> {
+        None
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct MirrorMaterial {
-    pub colour: Colour,
+    pub colour: ColourSource,
 }
 
 #[typetag::serde]
 impl Material for MirrorMaterial {
-    fn get_colour(
-        &self,
-        scene: &Scene,
-        view_direction: &Vector3<f32>,
-        position: &Point3<f32>,
-        normal: &Vector3<f32>,
-        ray_depth: u8,
-    ) -> Colour {
-        let view_direction_projected_on_normal = cgmath::dot(*view_direction, *normal) * normal;
-        let reflection = view_direction - 2.0 * view_direction_projected_on_normal;
-        let ray = Ray {
-            origin: position.clone(),
-            direction: reflection,
-        };
-
-        scene.cast_ray(&ray, ray_depth) * self.colour
+    fn scatter(&self, view_direction: &Vector3<f32>, hit: &Hit) -> Option<Scatter> {
+        Some(Scatter {
+            direction: reflect(view_direction, &hit.normal),
+            weight: self.colour.colour(hit),
+            pdf: None,
+        })
     }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DiffuseMaterial {
-    pub colour: Colour,
-    pub secondary_rays: i32,
+    pub colour: ColourSource,
 }
 
 #[typetag::serde]
 impl Material for DiffuseMaterial {
-    fn get_colour(
-        &self,
-        scene: &Scene,
-        _view_direction: &Vector3<f32>,
-        position: &Point3<f32>,
-        normal: &Vector3<f32>,
-        ray_depth: u8,
-    ) -> Colour {
-        let colours = (0..self.secondary_rays).map(|_| {
-            let random_direction = unit_vector_in_hemisphere(&normal);
-            let ray = Ray {
-                origin: position.clone(),
-                direction: random_direction,
-            };
-            scene.cast_ray(&ray, ray_depth)
-        });
-        let colour = colours.sum::<Colour>() / self.secondary_rays as f32;
+    fn scatter(&self, _view_direction: &Vector3<f32>, hit: &Hit) -> Option<Scatter> {
+        Some(scatter_diffuse(self.colour.colour(hit), hit))
+    }
+
+    fn bsdf(&self, _view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> Colour {
+        self.colour.colour(hit) * diffuse_pdf(hit, direction)
+    }
 
-        colour * self.colour
+    fn pdf(&self, _view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> f32 {
+        diffuse_pdf(hit, direction)
     }
 }
 
+/// Cosine weighted sampling, which cancels the cosine term and the 1/pi of a Lambertian BSDF.
+fn scatter_diffuse(albedo: Colour, hit: &Hit) -> Scatter {
+    let direction = unit_vector_in_hemisphere(&hit.normal);
+    Scatter {
+        direction,
+        weight: albedo,
+        pdf: Some(diffuse_pdf(hit, &direction)),
+    }
+}
+
+/// Density of cosine weighted sampling, which is also the Lambertian BSDF for a white surface
+/// times the cosine term.
+fn diffuse_pdf(hit: &Hit, direction: &Vector3<f32>) -> f32 {
+    cgmath::dot(hit.normal, direction.normalize()).max(0.0) / PI
+}
+
+/// Mirrors `direction` about the plane with the given normal.
+pub fn reflect(direction: &Vector3<f32>, normal: &Vector3<f32>) -> Vector3<f32> {
+    direction - 2.0 * cgmath::dot(*direction, *normal) * normal
+}
+
+/// Refracts `direction` through a surface whose normal faces against it, where `eta` is the
+/// ratio of the refractive indices on the incoming and outgoing side. Returns `None` on total
+/// internal reflection.
+pub fn refract(direction: &Vector3<f32>, normal: &Vector3<f32>, eta: f32) -> Option<Vector3<f32>> {
+    let cos_incident = -cgmath::dot(*direction, *normal);
+    let sin2_transmitted = eta * eta * (1.0 - cos_incident * cos_incident);
+    if sin2_transmitted > 1.0 {
+        return None;
+    }
+
+    let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+    Some(eta * direction + (eta * cos_incident - cos_transmitted) * normal)
+}
+
+/// Exact Fresnel reflectance of unpolarised light at a dielectric boundary, where `eta` is the
+/// ratio of the refractive indices on the incoming and outgoing side.
+pub fn fresnel_dielectric(cos_incident: f32, eta: f32) -> f32 {
+    let sin2_transmitted = eta * eta * (1.0 - cos_incident * cos_incident);
+    if sin2_transmitted >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+    let r_perpendicular =
+        (eta * cos_incident - cos_transmitted) / (eta * cos_incident + cos_transmitted);
+    let r_parallel =
+        (cos_incident - eta * cos_transmitted) / (cos_incident + eta * cos_transmitted);
+
+    0.5 * (r_perpendicular * r_perpendicular + r_parallel * r_parallel)
+}
+
 pub fn unit_vector_in_hemisphere(direction: &Vector3<f32>) -> Vector3<f32> {
     let mut rng = rand::thread_rng();
     loop {
@@ -91,26 +174,490 @@ pub fn unit_vector_in_hemisphere(direction: &Vector3<f32>) -> Vector3<f32> {
     }
 }
 
+/// Reflects or refracts `view_direction` through a smooth boundary with a material of index of
+/// refraction `index_of_refraction`, choosing between the two in proportion to the Fresnel
+/// reflectance.
+fn sample_dielectric(
+    view_direction: &Vector3<f32>,
+    hit: &Hit,
+    index_of_refraction: f32,
+) -> Vector3<f32> {
+    let view_direction = view_direction.normalize();
+    let eta = if hit.front_face {
+        1.0 / index_of_refraction
+    } else {
+        index_of_refraction
+    };
+    let cos_incident = -cgmath::dot(view_direction, hit.normal);
+    let reflectance = fresnel_dielectric(cos_incident, eta);
+
+    match refract(&view_direction, &hit.normal, eta) {
+        Some(refraction) if rand::thread_rng().gen::<f32>() >= reflectance => refraction,
+        _ => reflect(&view_direction, &hit.normal),
+    }
+}
+
+/// Glass-like material that reflects and refracts according to the Fresnel equations.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DielectricMaterial {
+    pub index_of_refraction: f32,
+    /// Beer–Lambert absorption coefficient per unit of distance travelled inside the material
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub absorption: Option<Colour>,
+}
+
+#[typetag::serde]
+impl Material for DielectricMaterial {
+    fn scatter(&self, view_direction: &Vector3<f32>, hit: &Hit) -> Option<Scatter> {
+        let direction = sample_dielectric(view_direction, hit, self.index_of_refraction);
+
+        // Light reaching the inside of the surface has travelled through the material
+        let weight = match self.absorption {
+            Some(absorption) if !hit.front_face => Colour {
+                r: (-absorption.r * hit.distance).exp(),
+                g: (-absorption.g * hit.distance).exp(),
+                b: (-absorption.b * hit.distance).exp(),
+                a: 1.0,
+            },
+            _ => WHITE,
+        };
+
+        Some(Scatter {
+            direction,
+            weight,
+            pdf: None,
+        })
+    }
+}
+
+/// A metal, given by its complex index of refraction `eta` + i `k` per colour channel.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum Metal {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+    Custom { eta: Colour, k: Colour },
+}
+
+impl Metal {
+    /// The real and imaginary parts of the index of refraction, at red, green and blue
+    /// wavelengths.
+    pub fn index_of_refraction(&self) -> (Colour, Colour) {
+        let rgb = |r, g, b| Colour { r, g, b, a: 1.0 };
+        match *self {
+            Metal::Gold => (rgb(0.143, 0.374, 1.442), rgb(3.983, 2.385, 1.603)),
+            Metal::Copper => (rgb(0.200, 0.924, 1.102), rgb(3.912, 2.452, 2.142)),
+            Metal::Aluminium => (rgb(1.657, 0.880, 0.521), rgb(9.224, 6.270, 4.837)),
+            Metal::Silver => (rgb(0.155, 0.117, 0.138), rgb(4.828, 3.122, 2.147)),
+            Metal::Custom { eta, k } => (eta, k),
+        }
+    }
+
+    /// Fresnel reflectance for light arriving at an angle with cosine `cos_incident`.
+    pub fn reflectance(&self, cos_incident: f32) -> Colour {
+        let (eta, k) = self.index_of_refraction();
+        Colour {
+            r: fresnel_conductor(cos_incident, eta.r, k.r),
+            g: fresnel_conductor(cos_incident, eta.g, k.g),
+            b: fresnel_conductor(cos_incident, eta.b, k.b),
+            a: 1.0,
+        }
+    }
+}
+
+/// Metal with a rough surface, modelled as GGX distributed microfacets that each reflect like a
+/// mirror. A `roughness` of zero gives a polished mirror, one a dull, almost diffuse sheen;
+/// `anisotropy` from 0 to 1 stretches highlights along the surface tangent, as on brushed metal.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ConductorMaterial {
+    pub metal: Metal,
+    #[serde(default = "default_roughness")]
+    pub roughness: f32,
+    #[serde(default)]
+    pub anisotropy: f32,
+}
+
+fn default_roughness() -> f32 {
+    0.2
+}
+
+impl ConductorMaterial {
+    fn distribution(&self) -> Ggx {
+        Ggx::new(self.roughness, self.anisotropy)
+    }
+}
+
+#[typetag::serde]
+impl Material for ConductorMaterial {
+    fn scatter(&self, view_direction: &Vector3<f32>, hit: &Hit) -> Option<Scatter> {
+        let ggx = self.distribution();
+        if ggx.is_smooth() {
+            let direction = reflect(view_direction, &hit.normal);
+            return Some(Scatter {
+                direction,
+                weight: self.metal.reflectance(cgmath::dot(direction, hit.normal)),
+                pdf: None,
+            });
+        }
+
+        let frame = ShadingFrame::new(hit);
+        let outgoing = frame.to_local(-view_direction.normalize());
+        if outgoing.z <= 0.0 {
+            return None;
+        }
+
+        let half = ggx.sample_visible_normal(outgoing);
+        let incoming = reflect(&-outgoing, &half);
+        if incoming.z <= 0.0 {
+            return None;
+        }
+
+        // The distribution and most of the masking cancel against the density of the sample
+        let weight = self.metal.reflectance(cgmath::dot(outgoing, half))
+            * (ggx.masking_shadowing(outgoing, incoming) / ggx.masking(outgoing));
+        Some(Scatter {
+            direction: frame.to_world(incoming),
+            weight,
+            pdf: Some(ggx.reflection_pdf(outgoing, half)),
+        })
+    }
+
+    fn bsdf(&self, view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> Colour {
+        let ggx = self.distribution();
+        let frame = ShadingFrame::new(hit);
+        let outgoing = frame.to_local(-view_direction.normalize());
+        let incoming = frame.to_local(direction.normalize());
+        if ggx.is_smooth() || outgoing.z <= 0.0 || incoming.z <= 0.0 {
+            return BLACK;
+        }
+
+        let half = (outgoing + incoming).normalize();
+        self.metal.reflectance(cgmath::dot(outgoing, half))
+            * (ggx.distribution(half) * ggx.masking_shadowing(outgoing, incoming)
+                / (4.0 * outgoing.z))
+    }
+
+    fn pdf(&self, view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> f32 {
+        let ggx = self.distribution();
+        let frame = ShadingFrame::new(hit);
+        let outgoing = frame.to_local(-view_direction.normalize());
+        let incoming = frame.to_local(direction.normalize());
+        if ggx.is_smooth() || incoming.z <= 0.0 {
+            return 0.0;
+        }
+
+        ggx.reflection_pdf(outgoing, (outgoing + incoming).normalize())
+    }
+}
+
+/// Roughness of the clearcoat layer of `PrincipledMaterial`.
+const CLEARCOAT_ROUGHNESS: f32 = 0.1;
+
+/// One material for most real-world surfaces, after the Disney principled BRDF. Every parameter
+/// runs from 0 to 1 and can be a texture:
+///
+/// - `base_colour` is the albedo of dielectrics and the reflectance of metals
+/// - `metallic` blends from a dielectric to a metal
+/// - `roughness` spreads out highlights, from a mirror finish to a dull one
+/// - `specular` scales the reflectance of dielectrics, with 0.5 the 4% of most materials
+/// - `sheen` adds the soft rim of cloth
+/// - `clearcoat` adds a second, glossy layer of varnish
+/// - `transmission` lets light through, like glass of `index_of_refraction` tinted by the base
+///   colour; transmission ignores roughness
+///
+/// `emission` is light given off by the surface.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PrincipledMaterial {
+    #[serde(default = "default_base_colour")]
+    pub base_colour: ColourSource,
+    #[serde(default)]
+    pub metallic: ScalarSource,
+    #[serde(default = "default_half")]
+    pub roughness: ScalarSource,
+    #[serde(default = "default_half")]
+    pub specular: ScalarSource,
+    #[serde(default)]
+    pub sheen: ScalarSource,
+    #[serde(default)]
+    pub clearcoat: ScalarSource,
+    #[serde(default)]
+    pub transmission: ScalarSource,
+    #[serde(default = "default_index_of_refraction")]
+    pub index_of_refraction: f32,
+    #[serde(default = "default_emission")]
+    pub emission: ColourSource,
+}
+
+fn default_base_colour() -> ColourSource {
+    colour::LIGHT_GREY.into()
+}
+
+fn default_half() -> ScalarSource {
+    0.5.into()
+}
+
+fn default_index_of_refraction() -> f32 {
+    1.5
+}
+
+fn default_emission() -> ColourSource {
+    BLACK.into()
+}
+
+/// The parameters of a `PrincipledMaterial` at a hit, turned into the lobes of its BSDF.
+struct PrincipledLobes {
+    base_colour: Colour,
+    roughness: f32,
+    sheen: f32,
+    clearcoat: f32,
+    /// Weight of the diffuse and sheen lobes
+    diffuse: f32,
+    /// Chance of the smooth glass lobe, which is handled separately from the others
+    glass: f32,
+    /// Reflectance of the specular lobe head on
+    specular_reflectance: Colour,
+    specular_distribution: Ggx,
+    clearcoat_distribution: Ggx,
+}
+
+impl PrincipledMaterial {
+    fn lobes(&self, hit: &Hit) -> PrincipledLobes {
+        let base_colour = self.base_colour.colour(hit);
+        let metallic = self.metallic.value(hit).clamp(0.0, 1.0);
+        let roughness = self.roughness.value(hit).clamp(0.0, 1.0);
+        let transmission = self.transmission.value(hit).clamp(0.0, 1.0);
+        let dielectric_reflectance = 0.08 * self.specular.value(hit).clamp(0.0, 1.0);
+
+        PrincipledLobes {
+            base_colour,
+            roughness,
+            sheen: self.sheen.value(hit).clamp(0.0, 1.0),
+            clearcoat: self.clearcoat.value(hit).clamp(0.0, 1.0),
+            diffuse: (1.0 - metallic) * (1.0 - transmission),
+            glass: (1.0 - metallic) * transmission,
+            specular_reflectance: Colour::lerp(
+                WHITE * dielectric_reflectance,
+                base_colour,
+                metallic,
+            ),
+            specular_distribution: Ggx::new(roughness, 0.0),
+            clearcoat_distribution: Ggx::new(CLEARCOAT_ROUGHNESS, 0.0),
+        }
+    }
+}
+
+impl PrincipledLobes {
+    /// Chances of sampling the diffuse, specular and clearcoat lobes, given that the glass lobe
+    /// was not picked.
+    fn probabilities(&self) -> (f32, f32, f32) {
+        let diffuse = self.diffuse;
+        let specular = 1.0 - 0.5 * self.diffuse;
+        let clearcoat = 0.25 * self.clearcoat;
+        let total = diffuse + specular + clearcoat;
+        (diffuse / total, specular / total, clearcoat / total)
+    }
+
+    /// The BSDF, excluding the glass lobe, times the cosine term. Directions are in the shading
+    /// frame.
+    fn evaluate(&self, outgoing: Vector3<f32>, incoming: Vector3<f32>) -> Colour {
+        if outgoing.z <= 0.0 || incoming.z <= 0.0 {
+            return BLACK;
+        }
+
+        let half = (outgoing + incoming).normalize();
+        let cos_difference = cgmath::dot(incoming, half);
+
+        // Disney diffuse, which darkens rough surfaces at grazing angles, with sheen on top
+        let retro_reflection = 0.5 + 2.0 * self.roughness * cos_difference * cos_difference;
+        let diffuse_fresnel = (1.0 + (retro_reflection - 1.0) * schlick_weight(incoming.z))
+            * (1.0 + (retro_reflection - 1.0) * schlick_weight(outgoing.z));
+        let diffuse = self.base_colour * (diffuse_fresnel / PI)
+            + WHITE * (self.sheen * schlick_weight(cos_difference));
+
+        let specular = fresnel_schlick(self.specular_reflectance, cos_difference)
+            * (self.specular_distribution.distribution(half)
+                * self
+                    .specular_distribution
+                    .masking_shadowing(outgoing, incoming)
+                / (4.0 * outgoing.z));
+
+        let clearcoat = 0.25
+            * self.clearcoat
+            * (0.04 + 0.96 * schlick_weight(cos_difference))
+            * self.clearcoat_distribution.distribution(half)
+            * self
+                .clearcoat_distribution
+                .masking_shadowing(outgoing, incoming)
+            / (4.0 * outgoing.z);
+
+        diffuse * (self.diffuse * incoming.z) + specular + WHITE * clearcoat
+    }
+
+    /// Density with which all lobes but glass together sample `incoming`.
+    fn pdf(&self, outgoing: Vector3<f32>, incoming: Vector3<f32>) -> f32 {
+        if outgoing.z <= 0.0 || incoming.z <= 0.0 {
+            return 0.0;
+        }
+
+        let half = (outgoing + incoming).normalize();
+        let (diffuse, specular, clearcoat) = self.probabilities();
+        (1.0 - self.glass)
+            * (diffuse * incoming.z / PI
+                + specular * self.specular_distribution.reflection_pdf(outgoing, half)
+                + clearcoat * self.clearcoat_distribution.reflection_pdf(outgoing, half))
+    }
+}
+
+/// Weight of the Fresnel term in Schlick's approximation.
+fn schlick_weight(cos_theta: f32) -> f32 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// Schlick's approximation of Fresnel reflectance, for reflectance `reflectance` head on.
+fn fresnel_schlick(reflectance: Colour, cos_theta: f32) -> Colour {
+    Colour::lerp(reflectance, WHITE, schlick_weight(cos_theta))
+}
+
+#[typetag::serde]
+impl Material for PrincipledMaterial {
+    fn emitted(&self, _view_direction: &Vector3<f32>, hit: &Hit) -> Colour {
+        self.emission.colour(hit)
+    }
+
+    fn scatter(&self, view_direction: &Vector3<f32>, hit: &Hit) -> Option<Scatter> {
+        let lobes = self.lobes(hit);
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f32>() < lobes.glass {
+            return Some(Scatter {
+                direction: sample_dielectric(view_direction, hit, self.index_of_refraction),
+                weight: lobes.base_colour,
+                pdf: None,
+            });
+        }
+
+        let frame = ShadingFrame::new(hit);
+        let outgoing = frame.to_local(-view_direction.normalize());
+        if outgoing.z <= 0.0 {
+            return None;
+        }
+
+        let (diffuse, specular, _) = lobes.probabilities();
+        let choice = rng.gen::<f32>();
+        let incoming = if choice < diffuse {
+            frame.to_local(unit_vector_in_hemisphere(&hit.normal))
+        } else {
+            let distribution = if choice < diffuse + specular {
+                &lobes.specular_distribution
+            } else {
+                &lobes.clearcoat_distribution
+            };
+            reflect(&-outgoing, &distribution.sample_visible_normal(outgoing))
+        };
+
+        let pdf = lobes.pdf(outgoing, incoming);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(Scatter {
+            direction: frame.to_world(incoming),
+            weight: lobes.evaluate(outgoing, incoming) / pdf,
+            pdf: Some(pdf),
+        })
+    }
+
+    fn bsdf(&self, view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> Colour {
+        let frame = ShadingFrame::new(hit);
+        self.lobes(hit).evaluate(
+            frame.to_local(-view_direction.normalize()),
+            frame.to_local(direction.normalize()),
+        )
+    }
+
+    fn pdf(&self, view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> f32 {
+        let frame = ShadingFrame::new(hit);
+        self.lobes(hit).pdf(
+            frame.to_local(-view_direction.normalize()),
+            frame.to_local(direction.normalize()),
+        )
+    }
+
+    fn is_emissive(&self) -> bool {
+        !matches!(
+            self.emission,
+            ColourSource::Constant(Colour { r, g, b, .. }) if r == 0.0 && g == 0.0 && b == 0.0
+        )
+    }
+
+    fn average_emission(&self) -> Colour {
+        self.emission.average()
+    }
+}
+
+/// Diffuse material with a black and white checker pattern.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CheckerMaterial {
     pub grid_size: f32,
+    #[serde(default)]
+    pub space: PatternSpace,
+}
+
+/// Coordinates a pattern is laid out in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum PatternSpace {
+    /// World space, so objects move through the pattern
+    #[default]
+    World,
+    /// The space of the object before any `Transform` placed it, so the pattern moves with it
+    Object,
+    /// The surface's UV coordinates, which follow the surface
+    Uv,
+}
+
+impl PatternSpace {
+    /// Where the hit lies in the space, with UV coordinates in the xy plane.
+    pub fn point(self, hit: &Hit) -> Point3<f32> {
+        match self {
+            PatternSpace::World => hit.position,
+            PatternSpace::Object => hit.object_position,
+            PatternSpace::Uv => Point3::new(hit.uv.x, hit.uv.y, 0.0),
+        }
+    }
 }
 
 #[typetag::serde]
 impl Material for CheckerMaterial {
-    fn get_colour(
-        &self,
-        _scene: &Scene,
-        _view_direction: &Vector3<f32>,
-        position: &Point3<f32>,
-        _normal: &Vector3<f32>,
-        _ray_depth: u8,
-    ) -> Colour {
-        let value_x = position.x.abs() % (2.0 * self.grid_size) < self.grid_size;
-        let value_y = position.y.abs() % (2.0 * self.grid_size) < self.grid_size;
-        let value_z = position.z.abs() % (2.0 * self.grid_size) < self.grid_size;
+    fn scatter(&self, _view_direction: &Vector3<f32>, hit: &Hit) -> Option<Scatter> {
+        Some(scatter_diffuse(self.albedo(hit), hit))
+    }
+
+    fn bsdf(&self, _view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> Colour {
+        self.albedo(hit) * diffuse_pdf(hit, direction)
+    }
 
-        if value_x ^ value_y ^ value_z {
+    fn pdf(&self, _view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> f32 {
+        diffuse_pdf(hit, direction)
+    }
+}
+
+impl CheckerMaterial {
+    fn albedo(&self, hit: &Hit) -> Colour {
+        let is_white = match self.space {
+            PatternSpace::World | PatternSpace::Object => {
+                let position = self.space.point(hit);
+                let value_x = position.x.abs() % (2.0 * self.grid_size) < self.grid_size;
+                let value_y = position.y.abs() % (2.0 * self.grid_size) < self.grid_size;
+                let value_z = position.z.abs() % (2.0 * self.grid_size) < self.grid_size;
+                value_x ^ value_y ^ value_z
+            }
+            PatternSpace::Uv => {
+                let cell = (hit.uv / self.grid_size).map(f32::floor);
+                (cell.x + cell.y).rem_euclid(2.0) == 0.0
+            }
+        };
+
+        if is_white {
             colour::WHITE
         } else {
             colour::BLACK
@@ -120,20 +667,139 @@ impl Material for CheckerMaterial {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LightMaterial {
-    pub colour: Colour,
+    pub colour: ColourSource,
 }
 
 #[typetag::serde]
 impl Material for LightMaterial {
-    fn get_colour(
-        &self,
-        _scene: &Scene,
-        _view_direction: &Vector3<f32>,
-        _position: &Point3<f32>,
-        _normal: &Vector3<f32>,
-        _ray_depth: u8,
-    ) -> Colour {
-        self.colour
+    fn emitted(&self, _view_direction: &Vector3<f32>, hit: &Hit) -> Colour {
+        self.colour.colour(hit)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn average_emission(&self) -> Colour {
+        self.colour.average()
+    }
+}
+
+/// Luminous efficacy of light at 555 nm, the most lumens a watt of light can give.
+const MAX_LUMINOUS_EFFICACY: f32 = 683.0;
+
+/// What the `intensity` of an `EmissiveMaterial` measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum EmissionUnit {
+    /// Scales the colour as it is
+    #[default]
+    Scale,
+    /// Luminance in candela per square metre, taking scene radiance to be in nits
+    Nits,
+    /// Power in watts given off by each square metre of an emitting side, counted at 683 lumens
+    /// per watt
+    Watts,
+}
+
+/// Light given off by a surface, on top of the light `material` reflects. Any primitive can be
+/// made a light by giving it this material, and sampled directly if the primitive supports it.
+///
+/// The emitted colour is `colour`, a constant or a texture, tinted by the colour of a black body
+/// at `temperature` kelvin if given. With an `EmissionUnit` other than `Scale`, the colour is
+/// scaled to an average luminance of one, so that `intensity` alone sets the brightness.
+///
+/// Surfaces emit from the side their normal points to, unless `two_sided`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EmissiveMaterial {
+    #[serde(default = "default_emission_colour")]
+    pub colour: ColourSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default = "default_emission_intensity")]
+    pub intensity: f32,
+    #[serde(default)]
+    pub unit: EmissionUnit,
+    #[serde(default)]
+    pub two_sided: bool,
+    /// How the surface reflects light; black if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<Arc<dyn Material>>,
+}
+
+fn default_emission_colour() -> ColourSource {
+    WHITE.into()
+}
+
+fn default_emission_intensity() -> f32 {
+    1.0
+}
+
+impl EmissiveMaterial {
+    /// Factor from the colour to the light emitted, tint included, or `None` if the colour is too
+    /// dark to bring to a brightness given in physical units.
+    fn emission_scale(&self) -> Option<Colour> {
+        let tint = self.temperature.map_or(WHITE, blackbody);
+        // Physical units set the brightness of the colour on average, so that a texture still
+        // varies over the surface
+        let average_luminance = || luminance(&(self.colour.average() * tint));
+        let scale = match self.unit {
+            EmissionUnit::Scale => self.intensity,
+            EmissionUnit::Nits => self.intensity / average_luminance(),
+            // Exitance M from a side that looks equally bright from everywhere gives radiance M / π
+            EmissionUnit::Watts => {
+                self.intensity * MAX_LUMINOUS_EFFICACY / (PI * average_luminance())
+            }
+        };
+        scale.is_finite().then(|| tint * scale)
+    }
+}
+
+#[typetag::serde]
+impl Material for EmissiveMaterial {
+    fn emitted(&self, view_direction: &Vector3<f32>, hit: &Hit) -> Colour {
+        let material_emission = self
+            .material
+            .as_ref()
+            .map_or(BLACK, |material| material.emitted(view_direction, hit));
+        if !hit.front_face && !self.two_sided {
+            return material_emission;
+        }
+
+        match self.emission_scale() {
+            Some(scale) => material_emission + self.colour.colour(hit) * scale,
+            None => material_emission,
+        }
+    }
+
+    fn scatter(&self, view_direction: &Vector3<f32>, hit: &Hit) -> Option<Scatter> {
+        self.material.as_ref()?.scatter(view_direction, hit)
+    }
+
+    fn bsdf(&self, view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> Colour {
+        self.material.as_ref().map_or(BLACK, |material| {
+            material.bsdf(view_direction, hit, direction)
+        })
+    }
+
+    fn pdf(&self, view_direction: &Vector3<f32>, hit: &Hit, direction: &Vector3<f32>) -> f32 {
+        self.material
+            .as_ref()
+            .map_or(0.0, |material| material.pdf(view_direction, hit, direction))
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn average_emission(&self) -> Colour {
+        let material_emission = self
+            .material
+            .as_ref()
+            .map_or(BLACK, |material| material.average_emission());
+        match self.emission_scale() {
+            Some(scale) => material_emission + self.colour.average() * scale,
+            None => material_emission,
+        }
     }
 }
 
@@ -145,14 +811,7 @@ pub struct SkyBoxMaterial {
 
 #[typetag::serde]
 impl Material for SkyBoxMaterial {
-    fn get_colour(
-        &self,
-        _scene: &Scene,
-        view_direction: &Vector3<f32>,
-        _position: &Point3<f32>,
-        _normal: &Vector3<f32>,
-        _ray_depth: u8,
-    ) -> Colour {
+    fn emitted(&self, view_direction: &Vector3<f32>, _hit: &Hit) -> Colour {
         Colour::lerp(
             self.colour_bottom,
             self.colour_top,
@@ -164,13 +823,15 @@ impl Material for SkyBoxMaterial {
 #[test]
 pub fn serialise_material() {
     let material_diffuse: &dyn Material = &DiffuseMaterial {
-        colour: colour::LIGHT_GREY,
-        secondary_rays: 64,
+        colour: colour::LIGHT_GREY.into(),
     };
 
     let _material_as_str = serde_json::to_string(&material_diffuse).unwrap();
 
-    assert_eq!(_material_as_str, "{\"DiffuseMaterial\":{\"colour\":{\"r\":0.75,\"g\":0.75,\"b\":0.75,\"a\":1.0},\"secondary_rays\":64}}");
+    assert_eq!(
+        _material_as_str,
+        "{\"DiffuseMaterial\":{\"colour\":{\"r\":0.75,\"g\":0.75,\"b\":0.75,\"a\":1.0}}}"
+    );
 }
 
 #[test]
@@ -180,3 +841,162 @@ pub fn deserialise_material() {
     )
     .unwrap();
 }
+
+#[test]
+pub fn sample_rough_conductor() {
+    use crate::ray::Ray;
+    use assert_approx_eq::assert_approx_eq;
+    use std::sync::Arc;
+
+    let material: Arc<dyn Material> = serde_json::from_str::<Box<dyn Material>>(
+        "{ \"ConductorMaterial\": { \"metal\": \"Gold\", \"roughness\": 0.4, \"anisotropy\": 0.5 } }",
+    )
+    .unwrap()
+    .into();
+    let view_direction = Vector3::new(0.3, -1.0, 0.2).normalize();
+    let hit = Hit::new(
+        &Ray::new(Point3::new(0.0, 1.0, 0.0), view_direction),
+        1.0,
+        Vector3::unit_y(),
+        material.clone(),
+    );
+
+    // The weight of each sample is the BSDF over the density it was sampled with
+    for _ in 0..100 {
+        let Some(scatter) = material.scatter(&view_direction, &hit) else {
+            continue;
+        };
+        let pdf = material.pdf(&view_direction, &hit, &scatter.direction);
+        let bsdf = material.bsdf(&view_direction, &hit, &scatter.direction);
+        assert_approx_eq!(scatter.pdf.unwrap(), pdf, pdf * 1e-3);
+        assert_approx_eq!(scatter.weight.g, bsdf.g / pdf, 1e-3);
+    }
+
+    // Gold reflects more red than blue
+    let reflectance = Metal::Gold.reflectance(1.0);
+    assert!(reflectance.r > 0.9 && reflectance.b < 0.5);
+}
+
+#[test]
+pub fn sample_principled_material() {
+    use crate::ray::Ray;
+    use assert_approx_eq::assert_approx_eq;
+    use std::sync::Arc;
+
+    let material: Arc<dyn Material> = serde_json::from_str::<Box<dyn Material>>(
+        "{ \"PrincipledMaterial\": {
+            \"base_colour\": { \"r\": 0.8, \"g\": 0.2, \"b\": 0.1, \"a\": 1 },
+            \"metallic\": 0.3, \"roughness\": 0.4, \"sheen\": 0.5, \"clearcoat\": 1,
+            \"transmission\": 0.2
+        } }",
+    )
+    .unwrap()
+    .into();
+    assert!(!material.is_emissive());
+
+    let view_direction = Vector3::new(0.3, -1.0, 0.2).normalize();
+    let hit = Hit::new(
+        &Ray::new(Point3::new(0.0, 1.0, 0.0), view_direction),
+        1.0,
+        Vector3::unit_y(),
+        material.clone(),
+    );
+
+    // Glossy samples weigh the BSDF over the density of all lobes, which light sampling also uses
+    let mut glossy_samples = 0;
+    for _ in 0..200 {
+        let Some(scatter) = material.scatter(&view_direction, &hit) else {
+            continue;
+        };
+        let Some(pdf) = scatter.pdf else {
+            continue;
+        };
+        glossy_samples += 1;
+        assert_approx_eq!(
+            material.pdf(&view_direction, &hit, &scatter.direction),
+            pdf,
+            pdf * 1e-3
+        );
+        let bsdf = material.bsdf(&view_direction, &hit, &scatter.direction);
+        assert_approx_eq!(scatter.weight.r, bsdf.r / pdf, 1e-3);
+    }
+    assert!(glossy_samples > 100);
+}
+
+#[test]
+pub fn emit_light() {
+    use crate::ray::Ray;
+    use crate::scene::TestDirectory;
+    use assert_approx_eq::assert_approx_eq;
+
+    let parse = |json: &str| -> Arc<dyn Material> {
+        serde_json::from_str::<Box<dyn Material>>(json)
+            .unwrap()
+            .into()
+    };
+    let hit_from = |material: &Arc<dyn Material>, z: f32| {
+        let ray = Ray::new(Point3::new(0.0, 0.0, z), Vector3::new(0.0, 0.0, -z));
+        Hit::new(&ray, z.abs(), Vector3::unit_z(), material.clone())
+    };
+
+    // One-sided emission leaves the back dark, apart from what the surface reflects
+    let material = parse(
+        "{ \"EmissiveMaterial\": {
+            \"intensity\": 100, \"unit\": \"Nits\", \"temperature\": 3000,
+            \"material\": { \"DiffuseMaterial\": { \"colour\": { \"r\": 0.5, \"g\": 0.5, \"b\": 0.5, \"a\": 1 } } }
+        } }",
+    );
+    let front = hit_from(&material, 1.0);
+    let emitted = material.emitted(&-Vector3::unit_z(), &front);
+    assert_approx_eq!(luminance(&emitted), 100.0, 1e-2);
+    assert!(emitted.r > emitted.b);
+    assert!(material.scatter(&-Vector3::unit_z(), &front).is_some());
+    let back = hit_from(&material, -1.0);
+    assert_eq!(luminance(&material.emitted(&Vector3::unit_z(), &back)), 0.0);
+
+    // A watt per square metre spread evenly over the hemisphere
+    let material = parse(
+        "{ \"EmissiveMaterial\": { \"intensity\": 1, \"unit\": \"Watts\", \"two_sided\": true } }",
+    );
+    let back = hit_from(&material, -1.0);
+    assert_approx_eq!(
+        luminance(&material.emitted(&Vector3::unit_z(), &back)),
+        MAX_LUMINOUS_EFFICACY / PI,
+        1e-2
+    );
+    assert!(material.is_emissive());
+    assert!(material.scatter(&Vector3::unit_z(), &back).is_none());
+
+    // A texture keeps its pattern, with its average at the given luminance
+    let directory = TestDirectory::new("emission");
+    let file_name = directory.join("emission.exr");
+    image::Rgb32FImage::from_fn(2, 1, |x, _| image::Rgb([0.2 + 0.6 * x as f32; 3]))
+        .save(&file_name)
+        .unwrap();
+    let material = parse(&format!(
+        "{{ \"EmissiveMaterial\": {{
+            \"colour\": {{ \"ImageTexture\": {{ \"file_name\": {:?}, \"wrap\": \"Clamp\" }} }},
+            \"intensity\": 100, \"unit\": \"Nits\"
+        }} }}",
+        file_name.to_str().unwrap()
+    ));
+    let mut hit = hit_from(&material, 1.0);
+    hit.uv = cgmath::Vector2::new(0.25, 0.5);
+    let dim = luminance(&material.emitted(&-Vector3::unit_z(), &hit));
+    hit.uv = cgmath::Vector2::new(0.75, 0.5);
+    let bright = luminance(&material.emitted(&-Vector3::unit_z(), &hit));
+    assert_approx_eq!(bright / dim, 4.0, 1e-3);
+    assert_approx_eq!(bright, 160.0, 1e-2);
+}
+
+#[test]
+pub fn fresnel_reflectance() {
+    use assert_approx_eq::assert_approx_eq;
+
+    // 4% of light is reflected at normal incidence on glass, regardless of direction
+    assert_approx_eq!(fresnel_dielectric(1.0, 1.0 / 1.5), 0.04, 1e-6);
+    assert_approx_eq!(fresnel_dielectric(1.0, 1.5), 0.04, 1e-6);
+    // Total internal reflection beyond the critical angle
+    assert_eq!(fresnel_dielectric(0.5, 1.5), 1.0);
+    assert!(refract(&Vector3::new(0.866, -0.5, 0.0), &Vector3::unit_y(), 1.5).is_none());
+}
@@ -0,0 +1,506 @@
+use crate::{
+    aabb::Aabb,
+    bvh::BvhTree,
+    distribution::Distribution1D,
+    hit::Hit,
+    intersectable::{
+        interpolate_uv, sample_triangle, triangle_tangent, Intersectable, BARYCENTRIC_UVS,
+    },
+    light::{Emitter, SurfaceSample},
+    material::Material,
+    ray::Ray,
+    scene::resolve_path,
+};
+use cgmath::{InnerSpace, Point3, Vector2, Vector3, Zero};
+use rand::Rng;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// How a mesh is described in the scene file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MeshDescription {
+    /// Path to a Wavefront `.obj` file
+    pub file_name: String,
+    /// Material used for faces without a mapped `.mtl` material
+    pub material: Arc<dyn Material>,
+    /// Maps names of materials in the `.mtl` file to scene materials
+    #[serde(default)]
+    pub materials: HashMap<String, Arc<dyn Material>>,
+    /// Interpolate vertex normals across faces
+    #[serde(default = "default_smooth_shading")]
+    pub smooth_shading: bool,
+}
+
+fn default_smooth_shading() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MeshTriangle {
+    vertices: [usize; 3],
+    material: usize,
+    /// Whether the vertices have texture coordinates from the file
+    textured: bool,
+}
+
+/// Vertex and face buffers shared by all triangles of a mesh.
+pub struct MeshData {
+    positions: Vec<Point3<f32>>,
+    normals: Vec<Vector3<f32>>,
+    texture_coordinates: Vec<Vector2<f32>>,
+    triangles: Vec<MeshTriangle>,
+    materials: Vec<Arc<dyn Material>>,
+}
+
+impl fmt::Debug for MeshData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeshData")
+            .field("vertices", &self.positions.len())
+            .field("triangles", &self.triangles.len())
+            .finish()
+    }
+}
+
+impl MeshData {
+    fn triangle_vertices(&self, index: usize) -> [Point3<f32>; 3] {
+        self.triangles[index]
+            .vertices
+            .map(|vertex| self.positions[vertex])
+    }
+
+    fn triangle_bounds(&self, triangle: &MeshTriangle) -> Aabb {
+        Aabb::from_points(triangle.vertices.map(|vertex| self.positions[vertex]))
+    }
+
+    /// Möller–Trumbore intersection, interpolating vertex normals when `smooth_shading` is set.
+    fn intersect_triangle(&self, index: usize, ray: &Ray, smooth_shading: bool) -> Option<Hit> {
+        let triangle = &self.triangles[index];
+        let [a, b, c] = triangle.vertices.map(|vertex| self.positions[vertex]);
+        let edge_ab = b - a;
+        let edge_ac = c - a;
+
+        let p = ray.direction.cross(edge_ac);
+        let determinant = edge_ab.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            // Ray is parallel to triangle
+            return None;
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+        let s = ray.origin - a;
+        let u = s.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge_ab);
+        let v = ray.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge_ac.dot(q) * inverse_determinant;
+        if distance < 0.0 {
+            return None;
+        }
+
+        let uvs = if triangle.textured {
+            triangle
+                .vertices
+                .map(|vertex| self.texture_coordinates[vertex])
+        } else {
+            BARYCENTRIC_UVS
+        };
+
+        // Which side of the face the ray is on, and so where secondary rays start, follows the
+        // face itself, so that interpolated normals tilted away from it cannot send rays through
+        let hit = Hit::new(
+            ray,
+            distance,
+            edge_ab.cross(edge_ac).normalize(),
+            self.materials[triangle.material].clone(),
+        )
+        .with_uv(interpolate_uv(uvs, u, v))
+        .with_tangent(triangle_tangent(edge_ab, edge_ac, uvs));
+        if !smooth_shading {
+            return Some(hit);
+        }
+
+        let [normal_a, normal_b, normal_c] = triangle.vertices.map(|vertex| self.normals[vertex]);
+        Some(hit.with_shading_normal((1.0 - u - v) * normal_a + u * normal_b + v * normal_c))
+    }
+}
+
+/// A triangle mesh loaded from a Wavefront `.obj` file.
+///
+/// The triangles share one vertex buffer and are accelerated by their own bounding volume
+/// hierarchy, so a mesh is a single leaf in the scene hierarchy.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "MeshDescription")]
+pub struct Mesh {
+    description: MeshDescription,
+    data: Arc<MeshData>,
+    tree: Arc<BvhTree>,
+}
+
+impl Mesh {
+    pub fn load(description: MeshDescription) -> Result<Self, String> {
+        let (models, obj_materials) = tobj::load_obj(
+            resolve_path(&description.file_name),
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ..Default::default()
+            },
+        )
+        .map_err(|error| format!("failed to load mesh '{}': {}", description.file_name, error))?;
+
+        // A missing or broken .mtl file only loses the material mapping
+        let obj_materials = obj_materials.unwrap_or_default();
+        let mut materials = vec![description.material.clone()];
+        let material_indices: Vec<usize> = obj_materials
+            .iter()
+            .map(
+                |obj_material| match description.materials.get(&obj_material.name) {
+                    Some(material) => {
+                        materials.push(material.clone());
+                        materials.len() - 1
+                    }
+                    None => 0,
+                },
+            )
+            .collect();
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut texture_coordinates = Vec::new();
+        let mut triangles = Vec::new();
+        for model in &models {
+            let mesh = &model.mesh;
+            let first_vertex = positions.len();
+            let material = mesh
+                .material_id
+                .and_then(|id| material_indices.get(id).copied())
+                .unwrap_or(0);
+
+            positions.extend(
+                mesh.positions
+                    .chunks_exact(3)
+                    .map(|p| Point3::new(p[0], p[1], p[2])),
+            );
+            if mesh.normals.len() == mesh.positions.len() {
+                normals.extend(
+                    mesh.normals
+                        .chunks_exact(3)
+                        .map(|n| normalize_or_zero(Vector3::new(n[0], n[1], n[2]))),
+                );
+            } else {
+                normals.extend(compute_vertex_normals(
+                    &positions[first_vertex..],
+                    &mesh.indices,
+                ));
+            }
+            let textured = mesh.texcoords.len() / 2 == mesh.positions.len() / 3;
+            if textured {
+                texture_coordinates.extend(
+                    mesh.texcoords
+                        .chunks_exact(2)
+                        .map(|uv| Vector2::new(uv[0], uv[1])),
+                );
+            } else {
+                texture_coordinates.resize(positions.len(), Vector2::zero());
+            }
+            triangles.extend(mesh.indices.chunks_exact(3).map(|indices| MeshTriangle {
+                vertices: [
+                    first_vertex + indices[0] as usize,
+                    first_vertex + indices[1] as usize,
+                    first_vertex + indices[2] as usize,
+                ],
+                material,
+                textured,
+            }));
+        }
+
+        let data = MeshData {
+            positions,
+            normals,
+            texture_coordinates,
+            triangles,
+            materials,
+        };
+        let bounds: Vec<Aabb> = data
+            .triangles
+            .iter()
+            .map(|triangle| data.triangle_bounds(triangle))
+            .collect();
+
+        Ok(Self {
+            description,
+            data: Arc::new(data),
+            tree: Arc::new(BvhTree::build(&bounds)),
+        })
+    }
+
+    pub fn number_of_triangles(&self) -> usize {
+        self.data.triangles.len()
+    }
+}
+
+/// Area weighted vertex normals, for meshes that do not specify their own.
+fn compute_vertex_normals(positions: &[Point3<f32>], indices: &[u32]) -> Vec<Vector3<f32>> {
+    let mut normals = vec![Vector3::zero(); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| i as usize);
+        let face_normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        normals[a] += face_normal;
+        normals[b] += face_normal;
+        normals[c] += face_normal;
+    }
+
+    normals.into_iter().map(normalize_or_zero).collect()
+}
+
+/// `normal` scaled to unit length, or left at zero for vertices no face gives a direction.
+fn normalize_or_zero(normal: Vector3<f32>) -> Vector3<f32> {
+    if normal.magnitude2() > 0.0 {
+        normal.normalize()
+    } else {
+        normal
+    }
+}
+
+impl TryFrom<MeshDescription> for Mesh {
+    type Error = String;
+
+    fn try_from(description: MeshDescription) -> Result<Self, Self::Error> {
+        Mesh::load(description)
+    }
+}
+
+impl Serialize for Mesh {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.description.serialize(serializer)
+    }
+}
+
+#[typetag::serde]
+impl Intersectable for Mesh {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        self.tree.intersect(ray, |index, ray| {
+            self.data
+                .intersect_triangle(index, ray, self.description.smooth_shading)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.tree.bounds()
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        for (material_index, material) in self.data.materials.iter().enumerate() {
+            if material.is_emissive() {
+                let emitter =
+                    MeshEmitter::new(self.data.clone(), self.tree.clone(), material_index);
+                emitters.extend(emitter.map(|emitter| Arc::new(emitter) as Arc<dyn Emitter>));
+            }
+        }
+    }
+}
+
+/// The triangles of a mesh with one emissive material, sampled together as a single light by
+/// picking triangles in proportion to their area.
+#[derive(Debug)]
+struct MeshEmitter {
+    data: Arc<MeshData>,
+    tree: Arc<BvhTree>,
+    material: usize,
+    triangles: Vec<usize>,
+    distribution: Distribution1D,
+    area: f32,
+}
+
+impl MeshEmitter {
+    /// The emitter over the triangles with `material`, or `None` if there are none.
+    fn new(data: Arc<MeshData>, tree: Arc<BvhTree>, material: usize) -> Option<Self> {
+        let triangles: Vec<usize> = (0..data.triangles.len())
+            .filter(|&index| data.triangles[index].material == material)
+            .collect();
+        if triangles.is_empty() {
+            return None;
+        }
+
+        let areas: Vec<f32> = triangles
+            .iter()
+            .map(|&index| {
+                let [a, b, c] = data.triangle_vertices(index);
+                0.5 * (b - a).cross(c - a).magnitude()
+            })
+            .collect();
+        let area = areas.iter().sum();
+
+        Some(Self {
+            data,
+            tree,
+            material,
+            triangles,
+            distribution: Distribution1D::new(areas),
+            area,
+        })
+    }
+}
+
+impl Emitter for MeshEmitter {
+    fn sample_surface(&self) -> SurfaceSample {
+        let (index, _) = self.distribution.sample(rand::thread_rng().gen());
+        let [a, b, c] = self.data.triangle_vertices(self.triangles[index]);
+        SurfaceSample {
+            position: sample_triangle(a, b, c),
+            normal: (b - a).cross(c - a).normalize(),
+            pdf: 1.0 / self.area,
+        }
+    }
+
+    fn surface_pdf(&self, _hit: &Hit) -> f32 {
+        1.0 / self.area
+    }
+
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit> {
+        // Light leaves along the face normals, whatever the shading normals
+        self.tree.intersect(ray, |index, ray| {
+            if self.data.triangles[index].material != self.material {
+                return None;
+            }
+            self.data.intersect_triangle(index, ray, false)
+        })
+    }
+
+    fn area(&self) -> f32 {
+        self.area
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        &self.data.materials[self.material]
+    }
+}
+
+#[test]
+pub fn load_and_intersect_mesh() {
+    use crate::scene::TestDirectory;
+
+    let directory = TestDirectory::new("mesh");
+    let file_name = directory.join("quad.obj");
+    std::fs::write(
+        &file_name,
+        "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nf 1 2 3 4\n",
+    )
+    .unwrap();
+
+    let mesh: Box<dyn Intersectable> = serde_json::from_str(&format!(
+        "{{ \"Mesh\": {{
+            \"file_name\": {:?},
+            \"material\": {{ \"LightMaterial\": {{ \"colour\": {{ \"r\": 1, \"g\": 1, \"b\": 1, \"a\": 1 }} }} }}
+        }} }}",
+        file_name.to_str().unwrap()
+    ))
+    .unwrap();
+
+    let ray = Ray::new(Point3::new(0.5, 0.5, 2.0), Vector3::new(0.0, 0.0, -1.0));
+    let hit = mesh.intersect(&ray).unwrap();
+    assert_eq!(hit.distance, 2.0);
+    assert!((hit.normal - Vector3::unit_z()).magnitude() < 1e-6);
+
+    let ray = Ray::new(Point3::new(1.5, 0.5, 2.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(mesh.intersect(&ray).is_none());
+
+    // Both triangles of the quad are sampled as one light
+    let mut emitters = Vec::new();
+    mesh.collect_emitters(&mut emitters);
+    assert_eq!(emitters.len(), 1);
+    let sample = emitters[0].sample_surface();
+    assert_eq!(sample.pdf, 0.25);
+    assert!(sample.position.x.abs() <= 1.0 && sample.position.y.abs() <= 1.0);
+}
+
+#[test]
+pub fn shade_smooth_meshes_by_face_side() {
+    use crate::scene::TestDirectory;
+
+    // One triangle with vertex normals tilted far from its face, and one with zero normals
+    let directory = TestDirectory::new("smooth_mesh");
+    let file_name = directory.join("triangles.obj");
+    std::fs::write(
+        &file_name,
+        "v -1 -1 0\nv 1 -1 0\nv 0 1 0\nv 2 -1 0\nv 4 -1 0\nv 3 1 0\n\
+         vn 1 0 0.2\nvn 0 0 0\n\
+         f 1//1 2//1 3//1\nf 4//2 5//2 6//2\n",
+    )
+    .unwrap();
+    let mesh: Box<dyn Intersectable> = serde_json::from_str(&format!(
+        "{{ \"Mesh\": {{
+            \"file_name\": {:?},
+            \"material\": {{ \"LightMaterial\": {{ \"colour\": {{ \"r\": 1, \"g\": 1, \"b\": 1, \"a\": 1 }} }} }}
+        }} }}",
+        file_name.to_str().unwrap()
+    ))
+    .unwrap();
+
+    // A grazing ray from above hits the front, even though it runs along the shading normal, and
+    // rays leaving back above the face start above it
+    let ray = Ray::new(Point3::new(-5.0, 0.0, 0.5), Vector3::new(1.0, 0.0, -0.1));
+    let hit = mesh.intersect(&ray).unwrap();
+    assert!(hit.front_face);
+    assert!((hit.geometric_normal - Vector3::unit_z()).magnitude() < 1e-6);
+    assert!(hit.normal.x > 0.9 && hit.normal.z > 0.0);
+    assert!(hit.spawn_ray(Vector3::new(1.0, 0.0, 0.1)).origin.z > 0.0);
+
+    // From below, the shading normal turns to face down with the face
+    let ray = Ray::new(Point3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 0.0, 1.0));
+    let hit = mesh.intersect(&ray).unwrap();
+    assert!(!hit.front_face);
+    assert!(hit.normal.z < 0.0);
+
+    // Zero vertex normals leave the face normal
+    let ray = Ray::new(Point3::new(3.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+    let hit = mesh.intersect(&ray).unwrap();
+    assert!((hit.normal - Vector3::unit_z()).magnitude() < 1e-6);
+}
+
+#[test]
+pub fn intersect_only_the_triangles_of_a_mesh_emitter() {
+    use crate::scene::TestDirectory;
+
+    // An emissive quad under a plain one, only the first mapped to a scene material
+    let directory = TestDirectory::new("mesh_emitter");
+    std::fs::write(directory.join("quads.mtl"), "newmtl lamp\nnewmtl cover\n").unwrap();
+    let file_name = directory.join("quads.obj");
+    std::fs::write(
+        &file_name,
+        "mtllib quads.mtl\n\
+         v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\n\
+         v -1 -1 1\nv 1 -1 1\nv 1 1 1\nv -1 1 1\n\
+         o lamp\nusemtl lamp\nf 1 2 3 4\n\
+         o cover\nusemtl cover\nf 5 6 7 8\n",
+    )
+    .unwrap();
+    let mesh: Box<dyn Intersectable> = serde_json::from_str(&format!(
+        "{{ \"Mesh\": {{
+            \"file_name\": {:?},
+            \"material\": {{ \"DiffuseMaterial\": {{ \"colour\": {{ \"r\": 1, \"g\": 1, \"b\": 1, \"a\": 1 }} }} }},
+            \"materials\": {{
+                \"lamp\": {{ \"LightMaterial\": {{ \"colour\": {{ \"r\": 1, \"g\": 1, \"b\": 1, \"a\": 1 }} }} }}
+            }}
+        }} }}",
+        file_name.to_str().unwrap()
+    ))
+    .unwrap();
+
+    let mut emitters = Vec::new();
+    mesh.collect_emitters(&mut emitters);
+    assert_eq!(emitters.len(), 1);
+    assert_eq!(emitters[0].area(), 4.0);
+
+    // The mesh is hit on the cover first, and the emitter sees through it to the lamp
+    let ray = Ray::new(Point3::new(0.5, 0.5, 2.0), Vector3::new(0.0, 0.0, -1.0));
+    assert_eq!(mesh.intersect(&ray).unwrap().distance, 1.0);
+    assert_eq!(emitters[0].intersect_surface(&ray).unwrap().distance, 2.0);
+}
@@ -0,0 +1,193 @@
+use crate::hit::Hit;
+use cgmath::{InnerSpace, Vector3};
+use rand::Rng;
+use std::f32::consts::PI;
+
+/// Roughness below which a microfacet surface is treated as a perfect mirror.
+const SMOOTH_ALPHA: f32 = 1e-3;
+
+/// Orthonormal frame at a hit, with z along the shading normal and x along the tangent, in which
+/// microfacet distributions are evaluated.
+#[derive(Debug, Clone, Copy)]
+pub struct ShadingFrame {
+    tangent: Vector3<f32>,
+    bitangent: Vector3<f32>,
+    normal: Vector3<f32>,
+}
+
+impl ShadingFrame {
+    pub fn new(hit: &Hit) -> Self {
+        Self {
+            tangent: hit.tangent,
+            bitangent: hit.normal.cross(hit.tangent),
+            normal: hit.normal,
+        }
+    }
+
+    pub fn to_local(&self, vector: Vector3<f32>) -> Vector3<f32> {
+        Vector3::new(
+            cgmath::dot(vector, self.tangent),
+            cgmath::dot(vector, self.bitangent),
+            cgmath::dot(vector, self.normal),
+        )
+    }
+
+    pub fn to_world(&self, vector: Vector3<f32>) -> Vector3<f32> {
+        self.tangent * vector.x + self.bitangent * vector.y + self.normal * vector.z
+    }
+}
+
+/// The GGX, or Trowbridge-Reitz, distribution of microfacet normals, possibly stretched along the
+/// tangent. Directions are given in a `ShadingFrame`, pointing away from the surface.
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    alpha_x: f32,
+    alpha_y: f32,
+}
+
+impl Ggx {
+    /// Distribution for a perceptual `roughness` from 0 to 1. `anisotropy` from 0 to 1 stretches
+    /// highlights along the tangent.
+    pub fn new(roughness: f32, anisotropy: f32) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        Self {
+            alpha_x: (alpha / aspect).max(SMOOTH_ALPHA),
+            alpha_y: (alpha * aspect).max(SMOOTH_ALPHA),
+        }
+    }
+
+    /// Whether the surface is smooth enough to be treated as a perfect mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) <= SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normal `normal` per unit solid angle of the surface.
+    pub fn distribution(&self, normal: Vector3<f32>) -> f32 {
+        if normal.z <= 0.0 {
+            return 0.0;
+        }
+
+        let stretched = (normal.x / self.alpha_x).powi(2)
+            + (normal.y / self.alpha_y).powi(2)
+            + normal.z * normal.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * stretched * stretched)
+    }
+
+    /// Smith's auxiliary function, from which the masking of `direction` follows.
+    fn lambda(&self, direction: Vector3<f32>) -> f32 {
+        let tan2 = ((self.alpha_x * direction.x).powi(2) + (self.alpha_y * direction.y).powi(2))
+            / (direction.z * direction.z);
+        (-1.0 + (1.0 + tan2).sqrt()) * 0.5
+    }
+
+    /// Fraction of microfacets facing `normal` that are visible from `direction`.
+    pub fn masking(&self, direction: Vector3<f32>) -> f32 {
+        1.0 / (1.0 + self.lambda(direction))
+    }
+
+    /// Fraction of microfacets visible from both `outgoing` and `incoming`.
+    pub fn masking_shadowing(&self, outgoing: Vector3<f32>, incoming: Vector3<f32>) -> f32 {
+        1.0 / (1.0 + self.lambda(outgoing) + self.lambda(incoming))
+    }
+
+    /// Picks a microfacet normal in proportion to how much of it is visible from `outgoing`,
+    /// following Heitz, "Sampling the GGX Distribution of Visible Normals", 2018.
+    pub fn sample_visible_normal(&self, outgoing: Vector3<f32>) -> Vector3<f32> {
+        let mut rng = rand::thread_rng();
+
+        // Stretch the view direction so the distribution becomes a hemisphere
+        let view = Vector3::new(
+            self.alpha_x * outgoing.x,
+            self.alpha_y * outgoing.y,
+            outgoing.z,
+        )
+        .normalize();
+        let length2 = view.x * view.x + view.y * view.y;
+        let t1 = if length2 > 0.0 {
+            Vector3::new(-view.y, view.x, 0.0) / length2.sqrt()
+        } else {
+            Vector3::unit_x()
+        };
+        let t2 = view.cross(t1);
+
+        // Sample the projected area of the hemisphere seen from the view direction
+        let radius = rng.gen::<f32>().sqrt();
+        let phi = 2.0 * PI * rng.gen::<f32>();
+        let p1 = radius * phi.cos();
+        let s = 0.5 * (1.0 + view.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * radius * phi.sin();
+        let normal = t1 * p1 + t2 * p2 + view * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        Vector3::new(
+            self.alpha_x * normal.x,
+            self.alpha_y * normal.y,
+            normal.z.max(0.0),
+        )
+        .normalize()
+    }
+
+    /// Solid angle density with which reflecting `outgoing` about a visible normal from
+    /// `sample_visible_normal` gives the direction with half vector `half`.
+    pub fn reflection_pdf(&self, outgoing: Vector3<f32>, half: Vector3<f32>) -> f32 {
+        if outgoing.z <= 0.0 {
+            return 0.0;
+        }
+
+        self.masking(outgoing) * self.distribution(half) / (4.0 * outgoing.z)
+    }
+}
+
+/// Fresnel reflectance of unpolarised light at a conductor with complex index of refraction
+/// `eta` + i `k`, relative to the medium the light arrives from.
+pub fn fresnel_conductor(cos_incident: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_incident.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let r_perpendicular = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_parallel = r_perpendicular * (t3 - t4) / (t3 + t4);
+
+    0.5 * (r_perpendicular + r_parallel)
+}
+
+#[test]
+pub fn sample_ggx() {
+    use assert_approx_eq::assert_approx_eq;
+
+    // Projected microfacet area always adds up to the area of the surface
+    let ggx = Ggx::new(0.5, 0.6);
+    let mut rng = rand::thread_rng();
+    let samples = 200_000;
+    let projected_area: f32 = (0..samples)
+        .map(|_| {
+            let z = rng.gen::<f32>();
+            let phi = 2.0 * PI * rng.gen::<f32>();
+            let r = (1.0 - z * z).sqrt();
+            let normal = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+            ggx.distribution(normal) * normal.z * 2.0 * PI
+        })
+        .sum::<f32>()
+        / samples as f32;
+    assert_approx_eq!(projected_area, 1.0, 0.05);
+
+    // Sampled normals face the viewer, and the reflection stays above the surface on average
+    let outgoing = Vector3::new(0.6, 0.0, 0.8);
+    for _ in 0..100 {
+        let normal = ggx.sample_visible_normal(outgoing);
+        assert!(cgmath::dot(normal, outgoing) >= 0.0);
+        assert!(ggx.reflection_pdf(outgoing, normal) > 0.0);
+    }
+
+    // Polished metal reflects most light head on, and all of it at grazing angles
+    assert!(fresnel_conductor(1.0, 0.155, 4.828) > 0.9);
+    assert_approx_eq!(fresnel_conductor(0.0, 0.155, 4.828), 1.0, 1e-5);
+}
@@ -0,0 +1,247 @@
+use crate::{
+    colour::{Colour, BLACK, WHITE},
+    hit::Hit,
+    material::PatternSpace,
+    texture::{ColourSource, Texture},
+};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector2, Vector3, VectorSpace};
+use serde::{Deserialize, Serialize};
+
+/// Hashes the integer lattice point `x`, `y`, `z` to pseudo-random bits.
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^ (h >> 15)
+}
+
+/// Pseudo-random number in [0, 1) from the bits of `h`, after mixing them with `seed`.
+fn unit_float(h: u32, seed: u32) -> f32 {
+    let h = (h ^ seed).wrapping_mul(0x2c1b_3c6d);
+    (h >> 8) as f32 / (1 << 24) as f32
+}
+
+/// One of the twelve directions to the edge midpoints of a cube, picked by `h`.
+fn gradient(h: u32) -> Vector3<f32> {
+    match h % 12 {
+        0 => Vector3::new(1.0, 1.0, 0.0),
+        1 => Vector3::new(-1.0, 1.0, 0.0),
+        2 => Vector3::new(1.0, -1.0, 0.0),
+        3 => Vector3::new(-1.0, -1.0, 0.0),
+        4 => Vector3::new(1.0, 0.0, 1.0),
+        5 => Vector3::new(-1.0, 0.0, 1.0),
+        6 => Vector3::new(1.0, 0.0, -1.0),
+        7 => Vector3::new(-1.0, 0.0, -1.0),
+        8 => Vector3::new(0.0, 1.0, 1.0),
+        9 => Vector3::new(0.0, -1.0, 1.0),
+        10 => Vector3::new(0.0, 1.0, -1.0),
+        _ => Vector3::new(0.0, -1.0, -1.0),
+    }
+}
+
+/// Ken Perlin's improved gradient noise, smoothly varying between about -1 and 1 with features
+/// one unit across. It is zero at every integer lattice point.
+pub fn perlin(point: Point3<f32>) -> f32 {
+    let cell = point.map(f32::floor);
+    let offset = point - cell;
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+    let fade = offset.map(|t| t * t * t * (t * (t * 6.0 - 15.0) + 10.0));
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let to_point = offset - Vector3::new(dx as f32, dy as f32, dz as f32);
+        cgmath::dot(gradient(hash(x + dx, y + dy, z + dz)), to_point)
+    };
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let edge = |dy: i32, dz: i32| lerp(corner(0, dy, dz), corner(1, dy, dz), fade.x);
+    let face = |dz: i32| lerp(edge(0, dz), edge(1, dz), fade.y);
+    lerp(face(0), face(1), fade.z)
+}
+
+/// Fractal Brownian motion: `octaves` layers of Perlin noise, each `lacunarity` times finer and
+/// `gain` times weaker than the last.
+pub fn fbm(point: Point3<f32>, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    octaves_of(point, octaves, lacunarity, gain, perlin)
+}
+
+/// Like `fbm`, but summing the absolute value of each layer, which gives sharp creases where the
+/// noise crosses zero.
+pub fn turbulence(point: Point3<f32>, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    octaves_of(point, octaves, lacunarity, gain, |point| {
+        perlin(point).abs()
+    })
+}
+
+fn octaves_of<F>(point: Point3<f32>, octaves: u32, lacunarity: f32, gain: f32, noise: F) -> f32
+where
+    F: Fn(Point3<f32>) -> f32,
+{
+    let (mut sum, mut frequency, mut amplitude) = (0.0, 1.0, 1.0);
+    for _ in 0..octaves {
+        sum += amplitude * noise(Point3::from_vec(point.to_vec() * frequency));
+        frequency *= lacunarity;
+        amplitude *= gain;
+    }
+    sum
+}
+
+/// Worley, or cellular, noise: the distance from `point` to the nearest of a scattering of
+/// feature points, one in every unit cube.
+pub fn worley(point: Point3<f32>) -> f32 {
+    let cell = point.map(f32::floor);
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+    let mut nearest = f32::INFINITY;
+    for dz in -1..=1 {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let h = hash(x + dx, y + dy, z + dz);
+                let feature = Point3::new(
+                    (x + dx) as f32 + unit_float(h, 0x68e3_1da4),
+                    (y + dy) as f32 + unit_float(h, 0xb529_7a4d),
+                    (z + dz) as f32 + unit_float(h, 0x1b56_c4e9),
+                );
+                nearest = nearest.min((feature - point).magnitude());
+            }
+        }
+    }
+    nearest
+}
+
+/// The pattern a `NoiseTexture` draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum NoisePattern {
+    Perlin,
+    Fbm,
+    Turbulence,
+    Worley,
+    /// Stripes along x, bent by turbulence
+    Marble,
+    /// Rings around the y axis, bent by fBm
+    Wood,
+}
+
+/// A procedural pattern, blending between the colours `low` and `high`. Both can themselves be
+/// textures, so patterns can be layered.
+///
+/// The pattern is laid out in `space`, scaled so features are about `1 / scale` across. The
+/// layered patterns add `octaves` layers of noise, each `lacunarity` times finer and `gain`
+/// times weaker than the last.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NoiseTexture {
+    pub pattern: NoisePattern,
+    #[serde(default = "default_low")]
+    pub low: ColourSource,
+    #[serde(default = "default_high")]
+    pub high: ColourSource,
+    #[serde(default)]
+    pub space: PatternSpace,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    #[serde(default = "default_octaves")]
+    pub octaves: u32,
+    #[serde(default = "default_lacunarity")]
+    pub lacunarity: f32,
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+    /// How far turbulence bends the stripes of marble and the rings of wood
+    #[serde(default = "default_distortion")]
+    pub distortion: f32,
+}
+
+fn default_low() -> ColourSource {
+    BLACK.into()
+}
+
+fn default_high() -> ColourSource {
+    WHITE.into()
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_octaves() -> u32 {
+    4
+}
+
+fn default_lacunarity() -> f32 {
+    2.0
+}
+
+fn default_gain() -> f32 {
+    0.5
+}
+
+fn default_distortion() -> f32 {
+    5.0
+}
+
+impl NoiseTexture {
+    /// Value of the pattern at `point`, from 0 for `low` to 1 for `high`.
+    pub fn value(&self, point: Point3<f32>) -> f32 {
+        let point = Point3::from_vec(point.to_vec() * self.scale);
+        let (octaves, lacunarity, gain) = (self.octaves, self.lacunarity, self.gain);
+
+        let value = match self.pattern {
+            NoisePattern::Perlin => 0.5 + 0.5 * perlin(point),
+            NoisePattern::Fbm => 0.5 + 0.5 * fbm(point, octaves, lacunarity, gain),
+            NoisePattern::Turbulence => turbulence(point, octaves, lacunarity, gain),
+            NoisePattern::Worley => worley(point),
+            NoisePattern::Marble => {
+                let bend = self.distortion * turbulence(point, octaves, lacunarity, gain);
+                0.5 + 0.5 * (point.x + bend).sin()
+            }
+            NoisePattern::Wood => {
+                // Rings are closer together than the period of the marble stripes, so bend less
+                let bend = self.distortion * 0.1 * fbm(point, octaves, lacunarity, gain);
+                let rings = Vector2::new(point.x, point.z).magnitude() + bend;
+                rings.rem_euclid(1.0)
+            }
+        };
+        value.clamp(0.0, 1.0)
+    }
+}
+
+#[typetag::serde]
+impl Texture for NoiseTexture {
+    fn colour(&self, hit: &Hit) -> Colour {
+        let value = self.value(self.space.point(hit));
+        self.low.colour(hit).lerp(self.high.colour(hit), value)
+    }
+
+    /// Taken half way between `low` and `high`, as the patterns mostly are on average.
+    fn average(&self) -> Colour {
+        self.low.average().lerp(self.high.average(), 0.5)
+    }
+}
+
+#[test]
+pub fn evaluate_noise() {
+    // Perlin noise vanishes on the lattice, and is continuous between lattice points
+    assert_eq!(perlin(Point3::new(3.0, -2.0, 7.0)), 0.0);
+    let (a, b) = (
+        perlin(Point3::new(0.5, 0.25, 0.125)),
+        perlin(Point3::new(0.5001, 0.25, 0.125)),
+    );
+    assert!((a - b).abs() < 1e-3);
+    assert!(a.abs() <= 1.0);
+
+    // Every unit cube has a feature point, so none is further than the diagonal of two cubes
+    for i in 0..100 {
+        let distance = worley(Point3::new(i as f32 * 0.37, i as f32 * -0.91, 0.5));
+        assert!((0.0..=2.0 * 3f32.sqrt()).contains(&distance));
+    }
+
+    let texture: NoiseTexture = serde_json::from_str(
+        "{ \"pattern\": \"Marble\", \"scale\": 4, \"high\": { \"r\": 0.8, \"g\": 0.8, \"b\": 0.8, \"a\": 1 } }",
+    )
+    .unwrap();
+    assert_eq!(texture.octaves, 4);
+    for i in 0..100 {
+        let value = texture.value(Point3::new(i as f32 * 0.13, 0.2, i as f32 * 0.07));
+        assert!((0.0..=1.0).contains(&value));
+    }
+}
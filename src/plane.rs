@@ -0,0 +1,162 @@
+use crate::{
+    aabb::Aabb,
+    hit::Hit,
+    intersectable::{orthonormal_basis, Intersectable},
+    light::{Emitter, SurfaceSample},
+    material::Material,
+    ray::Ray,
+};
+use cgmath::{InnerSpace, Point3, Vector2, Vector3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A plane through `point`, facing along `normal`. Given a `size` it is cut down to a rectangle
+/// centred on `point`, which makes it usable as an area light.
+///
+/// UV coordinates are distances from `point` along `tangent` and the bitangent (normal × tangent),
+/// so patterns tile at the same scale however far the plane extends.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Plane {
+    pub point: Point3<f32>,
+    pub normal: Vector3<f32>,
+    /// Direction of the U axis (and of the rectangle's width). Defaults to the world axis closest
+    /// to lying in the plane.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tangent: Option<Vector3<f32>>,
+    /// Width and height of the rectangle along the U and V axes; infinite if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<Vector2<f32>>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Plane {
+    /// The unit normal, tangent and bitangent of the plane.
+    fn frame(&self) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let normal = self.normal.normalize();
+        let (tangent, bitangent) = orthonormal_basis(normal, self.tangent);
+        (normal, tangent, bitangent)
+    }
+}
+
+#[typetag::serde]
+impl Intersectable for Plane {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let (normal, tangent, bitangent) = self.frame();
+
+        let denominator = cgmath::dot(normal, ray.direction);
+        if denominator.abs() < f32::EPSILON {
+            // Ray is parallel to plane
+            return None;
+        }
+
+        let distance = cgmath::dot(self.point - ray.origin, normal) / denominator;
+        if distance < 0.0 {
+            return None;
+        }
+
+        let offset = ray.origin + distance * ray.direction - self.point;
+        let uv = Vector2::new(cgmath::dot(offset, tangent), cgmath::dot(offset, bitangent));
+        if let Some(size) = self.size {
+            if uv.x.abs() > size.x * 0.5 || uv.y.abs() > size.y * 0.5 {
+                return None;
+            }
+        }
+
+        Some(
+            Hit::new(ray, distance, normal, self.material.clone())
+                .with_uv(uv)
+                .with_tangent(tangent),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let size = self.size?;
+        let (_, tangent, bitangent) = self.frame();
+        let (half_width, half_height) = (tangent * size.x * 0.5, bitangent * size.y * 0.5);
+        Some(Aabb::from_points([
+            self.point - half_width - half_height,
+            self.point + half_width - half_height,
+            self.point - half_width + half_height,
+            self.point + half_width + half_height,
+        ]))
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        // Only a rectangle has an area to sample
+        if self.size.is_some() && self.material.is_emissive() {
+            emitters.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+impl Emitter for Plane {
+    fn sample_surface(&self) -> SurfaceSample {
+        let mut rng = rand::thread_rng();
+        let (normal, tangent, bitangent) = self.frame();
+        let size = self.size.unwrap_or(Vector2::new(0.0, 0.0));
+
+        SurfaceSample {
+            position: self.point
+                + tangent * size.x * (rng.gen::<f32>() - 0.5)
+                + bitangent * size.y * (rng.gen::<f32>() - 0.5),
+            normal,
+            pdf: 1.0 / (size.x * size.y),
+        }
+    }
+
+    fn surface_pdf(&self, _hit: &Hit) -> f32 {
+        self.size.map_or(0.0, |size| 1.0 / (size.x * size.y))
+    }
+
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit> {
+        self.intersect(ray)
+    }
+
+    fn area(&self) -> f32 {
+        self.size.map_or(0.0, |size| size.x * size.y)
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        &self.material
+    }
+}
+
+#[test]
+pub fn intersect_plane() {
+    use crate::{colour, material::LightMaterial};
+
+    let mut plane = Plane {
+        point: Point3::new(0.0, -1.0, 0.0),
+        normal: Vector3::new(0.0, 1.0, 0.0),
+        tangent: None,
+        size: None,
+        material: Arc::new(LightMaterial {
+            colour: colour::WHITE.into(),
+        }),
+    };
+    assert!(plane.bounding_box().is_none());
+
+    let ray = Ray::new(Point3::new(3.0, 1.0, 2.0), Vector3::new(0.0, -1.0, 0.0));
+    let hit = plane.intersect(&ray).unwrap();
+    assert_eq!(hit.distance, 2.0);
+    assert_eq!(hit.normal, Vector3::new(0.0, 1.0, 0.0));
+    assert_eq!(hit.uv, Vector2::new(3.0, -2.0));
+
+    // A 2 by 2 rectangle no longer reaches the ray
+    plane.size = Some(Vector2::new(2.0, 2.0));
+    assert!(plane.intersect(&ray).is_none());
+    assert_eq!(
+        plane.bounding_box().unwrap(),
+        Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, -1.0, 1.0))
+    );
+
+    let hit = plane
+        .intersect(&Ray::new(
+            Point3::new(0.5, -2.0, 0.5),
+            Vector3::new(0.0, 1.0, 0.0),
+        ))
+        .unwrap();
+    assert!(!hit.front_face);
+    assert_eq!(hit.normal, Vector3::new(0.0, -1.0, 0.0));
+}
@@ -1,4 +1,5 @@
 use crate::colour::Colour;
+use std::io::{self, Write};
 
 pub fn write_ppm_image<I>(width: usize, height: usize, image: I) -> String
 where
@@ -11,9 +12,9 @@ where
     ppm_image.push_str(&ppm_image_header);
 
     for pixel in image {
-        let r = pixel.r * MAX_COLOUR_VALUE;
-        let g = pixel.g * MAX_COLOUR_VALUE;
-        let b = pixel.b * MAX_COLOUR_VALUE;
+        let r = (pixel.r * MAX_COLOUR_VALUE).round();
+        let g = (pixel.g * MAX_COLOUR_VALUE).round();
+        let b = (pixel.b * MAX_COLOUR_VALUE).round();
         let pixel_as_string = format!("{} {} {}\n", r as u8, g as u8, b as u8);
         ppm_image.push_str(&pixel_as_string);
     }
@@ -21,6 +22,32 @@ where
     ppm_image
 }
 
+/// Writes a binary (P6) PPM image, quantising colours in [0, 1] to 8 bits per channel.
+pub fn write_binary_ppm_image<W, I>(
+    writer: &mut W,
+    width: usize,
+    height: usize,
+    image: I,
+) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = Colour>,
+{
+    const MAX_COLOUR_VALUE: f32 = 255.0;
+
+    writeln!(writer, "P6 {} {} {}", width, height, MAX_COLOUR_VALUE)?;
+
+    for pixel in image {
+        writer.write_all(&[
+            (pixel.r * MAX_COLOUR_VALUE).round() as u8,
+            (pixel.g * MAX_COLOUR_VALUE).round() as u8,
+            (pixel.b * MAX_COLOUR_VALUE).round() as u8,
+        ])?;
+    }
+
+    writer.flush()
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -58,4 +85,24 @@ pub mod tests {
 
         println!("{}", ppm_image);
     }
+
+    #[test]
+    pub fn write_binary_ppm_image_test() {
+        let image = vec![
+            Colour {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 0.5,
+            };
+            4
+        ];
+
+        let mut binary_ppm_image = Vec::new();
+        write_binary_ppm_image(&mut binary_ppm_image, 2, 2, image).unwrap();
+
+        assert!(binary_ppm_image.starts_with(b"P6 2 2 255\n"));
+        assert_eq!(binary_ppm_image.len(), 11 + 2 * 2 * 3);
+        assert_eq!(binary_ppm_image[11..14], [26, 51, 77]);
+    }
 }
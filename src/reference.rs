@@ -0,0 +1,66 @@
+use crate::{
+    aabb::Aabb,
+    hit::Hit,
+    intersectable::{Intersectable, Interval},
+    light::Emitter,
+    ray::Ray,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// Stands in for one of the scene's named `definitions`, so the same model can be placed many
+/// times, usually below a `Transform`, while sharing one copy of its data.
+#[derive(Deserialize, Serialize)]
+pub struct Reference {
+    pub name: String,
+    /// Set once the scene resolves its references
+    #[serde(skip)]
+    target: Option<Arc<dyn Intersectable>>,
+}
+
+impl Reference {
+    pub fn new(name: String) -> Self {
+        Self { name, target: None }
+    }
+}
+
+impl fmt::Debug for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reference")
+            .field("name", &self.name)
+            .field("resolved", &self.target.is_some())
+            .finish()
+    }
+}
+
+#[typetag::serde]
+impl Intersectable for Reference {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        self.target.as_ref()?.intersect(ray)
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        self.target.as_ref()?.intervals(ray)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.target.as_ref()?.bounding_box()
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        if let Some(target) = &self.target {
+            target.collect_emitters(emitters);
+        }
+    }
+
+    fn resolve_references(
+        &mut self,
+        definitions: &HashMap<String, Arc<dyn Intersectable>>,
+    ) -> Result<(), String> {
+        let target = definitions
+            .get(&self.name)
+            .ok_or_else(|| self.name.clone())?;
+        self.target = Some(target.clone());
+        Ok(())
+    }
+}
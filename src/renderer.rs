@@ -1,14 +1,17 @@
 use crate::{
     camera::Camera,
     colour::{Colour, BLACK},
-    ray::Ray,
+    filter::{Filter, FilterSampler},
     scene::Scene,
+    viewport::Viewport,
 };
 use scoped_threadpool::Pool;
 
 pub struct Renderer {
     pub num_workers: usize,
     pub num_chunks: usize,
+    pub samples_per_pixel: usize,
+    pub filter: Filter,
     pub scene: Scene,
 }
 
@@ -17,25 +20,52 @@ pub struct Renderer {
 
 impl Renderer {
     pub fn render(&self, camera: &Camera, width: usize, height: usize) -> Vec<Colour> {
+        assert!(self.samples_per_pixel > 0, "no samples per pixel");
+        let filter = FilterSampler::new(self.filter);
         let image_size = width * height;
-        let chunk_size = image_size / self.num_chunks;
+        let chunk_size = (image_size / self.num_chunks).max(1);
         let mut image = vec![BLACK; image_size];
-        let rays: Vec<Ray> = camera.get_viewport(width, height).collect();
+        let viewport = camera.get_viewport(width, height);
 
         Pool::new(self.num_workers as u32).scoped(|scope| {
-            let ray_chunks = rays.chunks(chunk_size);
-            let image_chunks = image.chunks_mut(chunk_size);
-
-            let jobs = ray_chunks.zip(image_chunks);
-            for (rays, image_chunk) in jobs {
+            for (chunk_index, image_chunk) in image.chunks_mut(chunk_size).enumerate() {
+                let (viewport, filter) = (&viewport, &filter);
                 scope.execute(move || {
-                    rays.iter()
-                        .enumerate()
-                        .for_each(|(idx, ray)| image_chunk[idx] = self.scene.cast_ray(ray, 0));
+                    let first_pixel = chunk_index * chunk_size;
+                    image_chunk.iter_mut().enumerate().for_each(|(idx, pixel)| {
+                        let pixel_index = first_pixel + idx;
+                        *pixel = self.render_pixel(
+                            viewport,
+                            filter,
+                            pixel_index % width,
+                            pixel_index / width,
+                        )
+                    });
                 });
             }
         });
 
         image
     }
+
+    /// Filtered average of `samples_per_pixel` rays through the pixel.
+    ///
+    /// The rays are placed by the filter, so their weights already average to one and the sum is
+    /// divided by the number of rays rather than by the sum of the weights.
+    fn render_pixel(
+        &self,
+        viewport: &Viewport,
+        filter: &FilterSampler,
+        x: usize,
+        y: usize,
+    ) -> Colour {
+        let colour = viewport
+            .jittered_rays(x, y, self.samples_per_pixel, filter)
+            .fold(BLACK, |colour, (ray, weight)| {
+                let radiance = ray.map_or(BLACK, |ray| self.scene.cast_ray(&ray));
+                colour + radiance * weight
+            });
+
+        colour / self.samples_per_pixel as f32
+    }
 }
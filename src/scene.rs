@@ -1,16 +1,50 @@
 use cgmath::num_traits::identities::Zero;
 use cgmath::EuclideanSpace;
-use cgmath::{Point3, Vector3};
+use cgmath::{InnerSpace, Point3, Vector2, Vector3};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::colour::{Colour, BLACK};
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::camera::{Camera, CameraDescription};
+use crate::colour::{self, Colour, BLACK, WHITE};
+use crate::distribution::Distribution1D;
+use crate::hit::Hit;
 use crate::intersectable::Intersectable;
-use crate::material::Material;
+use crate::light::{power_heuristic, AreaLight, Light};
+use crate::material::{Material, SkyBoxMaterial};
 use crate::ray::Ray;
 
+/// Bounces a path must survive before Russian roulette may terminate it.
+const RUSSIAN_ROULETTE_DEPTH: u8 = 3;
+/// Keeps bright paths subject to Russian roulette, so they still terminate eventually.
+const MAX_SURVIVAL_PROBABILITY: f32 = 0.95;
+/// Relative slack on distances to lights, so shadow rays are not blocked by the light itself.
+const SHADOW_EPSILON: f32 = 1e-3;
+
 pub struct Scene {
+    camera: Camera,
     max_ray_depth: u8,
     root_intersectable: Box<dyn Intersectable>,
-    background: Box<dyn Material>,
+    background: Arc<dyn Material>,
+    /// Index in `lights` of the light sampling the background
+    background_light: Option<usize>,
+    lights: Vec<Arc<dyn Light>>,
+    /// Picks lights in proportion to their power
+    light_distribution: Distribution1D,
+    /// Indices in `lights` of the area lights on surfaces with each emissive material, keyed by
+    /// the address of the material
+    area_lights: HashMap<usize, Vec<usize>>,
+    /// Radius of the scene, against which the power of lights at infinity is measured
+    radius: f32,
 }
 
 #[derive(Copy, Clone)]
@@ -21,38 +55,705 @@ pub struct SceneStatistics {
 
 impl Scene {
     pub fn new(
+        camera: Camera,
         max_ray_depth: u8,
         root_intersectable: Box<dyn Intersectable>,
         background: Box<dyn Material>,
     ) -> Scene {
+        let mut emitters = Vec::new();
+        root_intersectable.collect_emitters(&mut emitters);
+
+        // Scenes with infinite planes are measured by their lights instead, and lights at
+        // infinity given at least a unit radius to shine on
+        let bounds = root_intersectable.bounding_box().unwrap_or_else(|| {
+            Aabb::from_points(
+                emitters
+                    .iter()
+                    .map(|emitter| emitter.sample_surface().position),
+            )
+        });
+        let radius = if bounds.is_empty() {
+            1.0
+        } else {
+            (0.5 * bounds.extent().magnitude()).max(1.0)
+        };
+
+        let mut area_lights: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, emitter) in emitters.iter().enumerate() {
+            area_lights
+                .entry(material_key(emitter.material()))
+                .or_default()
+                .push(index);
+        }
+        let mut lights: Vec<_> = emitters
+            .into_iter()
+            .map(|emitter| Arc::new(AreaLight { emitter }) as Arc<dyn Light>)
+            .collect();
+
+        let background_light = background.background_light().map(|light| {
+            lights.push(light);
+            lights.len() - 1
+        });
+
         Self {
+            camera,
             max_ray_depth,
             root_intersectable,
-            background,
+            background: background.into(),
+            background_light,
+            light_distribution: light_distribution(&lights, radius),
+            lights,
+            area_lights,
+            radius,
         }
     }
 
-    pub fn cast_ray(&self, ray: &Ray, ray_depth: u8) -> Colour {
-        if ray_depth > self.max_ray_depth {
+    /// Adds `lights` that are not part of the geometry, such as point lights, to the lights
+    /// sampled at every bounce.
+    pub fn with_lights(mut self, lights: impl IntoIterator<Item = Arc<dyn Light>>) -> Self {
+        self.lights.extend(lights);
+        self.light_distribution = light_distribution(&self.lights, self.radius);
+        self
+    }
+
+    /// The camera the scene file places in the scene.
+    pub fn camera(&self) -> Camera {
+        self.camera
+    }
+
+    /// Radiance arriving at the origin of `ray` from along its direction, estimated by following
+    /// a single path through the scene. Paths end when they escape, hit a material that does not
+    /// scatter, are terminated by Russian roulette or reach `max_ray_depth` bounces.
+    ///
+    /// Lights are sampled directly at every non-specular bounce, and combined with paths that hit
+    /// them by multiple importance sampling.
+    pub fn cast_ray(&self, ray: &Ray) -> Colour {
+        let mut rng = rand::thread_rng();
+        let mut ray = *ray;
+        let mut throughput = WHITE;
+        let mut radiance = BLACK;
+        // Density of the bounce that produced `ray`, or `None` for camera rays and specular bounces
+        let mut scatter_pdf: Option<f32> = None;
+
+        for ray_depth in 0..=self.max_ray_depth {
+            let hit = match self.root_intersectable.intersect(&ray) {
+                Some(hit) => hit,
+                None => {
+                    let background_hit = self.background_hit();
+                    let emitted = self.background.emitted(&ray.direction, &background_hit);
+                    let weight = match (scatter_pdf, self.background_light) {
+                        (Some(scatter_pdf), Some(index)) => {
+                            let light_pdf =
+                                self.lights[index].pdf(&ray).map_or(0.0, |(_, pdf)| pdf);
+                            power_heuristic(
+                                scatter_pdf,
+                                light_pdf * self.light_distribution.probability(index),
+                            )
+                        }
+                        _ => 1.0,
+                    };
+                    return radiance + throughput * emitted * weight;
+                }
+            };
+
+            // Only emissive materials are sampled as lights, so only they need weighting
+            let emitted = hit.material.emitted(&ray.direction, &hit);
+            let weight = match scatter_pdf {
+                Some(scatter_pdf) if hit.material.is_emissive() => {
+                    power_heuristic(scatter_pdf, self.light_pdf(&ray, &hit))
+                }
+                _ => 1.0,
+            };
+            radiance = radiance + throughput * emitted * weight;
+
+            let scatter = match hit.material.scatter(&ray.direction, &hit) {
+                Some(scatter) => scatter,
+                None => break,
+            };
+
+            if scatter.pdf.is_some() {
+                radiance = radiance + throughput * self.sample_light(&ray.direction, &hit);
+            }
+
+            throughput = throughput * scatter.weight;
+            scatter_pdf = scatter.pdf;
+
+            // Terminate dim paths at random, boosting the survivors to keep the estimate unbiased
+            if ray_depth >= RUSSIAN_ROULETTE_DEPTH {
+                let survival_probability = throughput
+                    .r
+                    .max(throughput.g)
+                    .max(throughput.b)
+                    .min(MAX_SURVIVAL_PROBABILITY);
+                if rng.gen::<f32>() >= survival_probability {
+                    break;
+                }
+                throughput = throughput * (1.0 / survival_probability);
+            }
+
+            ray = hit.spawn_ray(scatter.direction);
+        }
+
+        radiance
+    }
+
+    /// Light reflected back along `view_direction` from one light, picked at random in
+    /// proportion to its power, weighted for combination with paths that hit the light by
+    /// chance.
+    fn sample_light(&self, view_direction: &Vector3<f32>, hit: &Hit) -> Colour {
+        if self.lights.is_empty() {
             return BLACK;
         }
 
-        let hit = self.root_intersectable.intersect(ray);
-        match hit {
-            Some(hit) => hit.material.get_colour(
-                self,
-                &ray.direction,
-                &hit.position,
-                &hit.normal,
-                ray_depth + 1,
-            ),
-            None => self.background.get_colour(
-                self,
-                &ray.direction,
-                &Point3::<f32>::origin(),
-                &Vector3::<f32>::zero(),
-                ray_depth + 1,
-            ),
+        let (index, _) = self.light_distribution.sample(rand::thread_rng().gen());
+        let light = &self.lights[index];
+        let sample = match light.sample(&hit.position) {
+            Some(sample) if sample.pdf > 0.0 => sample,
+            _ => return BLACK,
+        };
+
+        let bsdf = hit.material.bsdf(view_direction, hit, &sample.direction);
+        if bsdf.r <= 0.0 && bsdf.g <= 0.0 && bsdf.b <= 0.0 {
+            return BLACK;
+        }
+
+        if self.is_occluded(&hit.spawn_ray(sample.direction), sample.distance) {
+            return BLACK;
+        }
+
+        let light_pdf = sample.pdf * self.light_distribution.probability(index);
+        let weight = if light.is_delta() {
+            1.0
+        } else {
+            let bsdf_pdf = hit.material.pdf(view_direction, hit, &sample.direction);
+            power_heuristic(light_pdf, bsdf_pdf)
+        };
+
+        bsdf * sample.radiance * (weight / light_pdf)
+    }
+
+    /// Density with which light sampling picks the emissive surface `ray` hits at `hit`.
+    fn light_pdf(&self, ray: &Ray, hit: &Hit) -> f32 {
+        // Only surfaces sharing the material of the hit can be the light, and those are usually
+        // just the one; instances of a definition placed more than once are told apart by how far
+        // along the ray they lie
+        let tolerance = SHADOW_EPSILON * hit.distance.max(1.0);
+        self.area_lights
+            .get(&material_key(&hit.material))
+            .into_iter()
+            .flatten()
+            .find_map(|&index| {
+                let (distance, pdf) = self.lights[index].pdf(ray)?;
+                ((distance - hit.distance).abs() <= tolerance)
+                    .then(|| pdf * self.light_distribution.probability(index))
+            })
+            .unwrap_or(0.0)
+    }
+
+    /// Whether anything lies along `ray` closer than `distance`.
+    fn is_occluded(&self, ray: &Ray, distance: f32) -> bool {
+        self.root_intersectable
+            .intersect(ray)
+            .is_some_and(|hit| hit.distance < distance * (1.0 - SHADOW_EPSILON))
+    }
+
+    fn background_hit(&self) -> Hit {
+        Hit {
+            distance: f32::INFINITY,
+            position: Point3::<f32>::origin(),
+            object_position: Point3::<f32>::origin(),
+            normal: Vector3::<f32>::zero(),
+            geometric_normal: Vector3::<f32>::zero(),
+            front_face: true,
+            uv: Vector2::zero(),
+            tangent: Vector3::<f32>::zero(),
+            material: self.background.clone(),
+        }
+    }
+}
+
+/// Distribution picking each of `lights` in proportion to its power.
+fn light_distribution(lights: &[Arc<dyn Light>], scene_radius: f32) -> Distribution1D {
+    Distribution1D::new(
+        lights
+            .iter()
+            .map(|light| light.power(scene_radius).max(0.0))
+            .collect(),
+    )
+}
+
+/// Identifies a material by its address, which the surfaces sharing it also share.
+fn material_key(material: &Arc<dyn Material>) -> usize {
+    Arc::as_ptr(material) as *const () as usize
+}
+
+#[derive(Debug)]
+pub enum SceneLoadError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    /// A `Reference` names a definition that does not exist, or definitions refer to each other
+    /// in a cycle
+    Reference(String),
+}
+
+impl fmt::Display for SceneLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneLoadError::Io(error) => write!(f, "failed to read scene: {}", error),
+            SceneLoadError::Parse(error) => write!(f, "failed to parse scene: {}", error),
+            SceneLoadError::Reference(name) => {
+                write!(f, "undefined or circular reference to '{}'", name)
+            }
         }
     }
 }
+
+impl Error for SceneLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneLoadError::Io(error) => Some(error),
+            SceneLoadError::Parse(error) => Some(error),
+            SceneLoadError::Reference(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for SceneLoadError {
+    fn from(error: io::Error) -> Self {
+        SceneLoadError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SceneLoadError {
+    fn from(error: serde_json::Error) -> Self {
+        SceneLoadError::Parse(error)
+    }
+}
+
+/// The contents of a scene file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub camera: CameraDescription,
+    #[serde(default = "default_max_ray_depth")]
+    pub max_ray_depth: u8,
+    #[serde(default = "default_background")]
+    pub background: Box<dyn Material>,
+    /// Lights besides those of emissive primitives and the background
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<Box<dyn Light>>,
+    /// Named intersectables, placed in the scene by `Reference`s to them
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub definitions: HashMap<String, Box<dyn Intersectable>>,
+    pub root: Box<dyn Intersectable>,
+}
+
+fn default_max_ray_depth() -> u8 {
+    16
+}
+
+fn default_background() -> Box<dyn Material> {
+    Box::new(SkyBoxMaterial {
+        colour_top: colour::LIGHT_BLUE,
+        colour_bottom: colour::WHITE,
+    })
+}
+
+impl SceneDescription {
+    /// Describes a scene holding just `root`, with everything else at its default.
+    pub fn from_root(root: Box<dyn Intersectable>) -> Self {
+        Self {
+            camera: CameraDescription::default(),
+            max_ray_depth: default_max_ray_depth(),
+            background: default_background(),
+            lights: Vec::new(),
+            definitions: HashMap::new(),
+            root,
+        }
+    }
+
+    /// Points the references in the scene at their definitions, each of which gets a hierarchy of
+    /// its own.
+    fn resolve_references(&mut self) -> Result<(), SceneLoadError> {
+        let mut resolved: HashMap<String, Arc<dyn Intersectable>> = HashMap::new();
+        let mut pending: Vec<_> = self.definitions.drain().collect();
+
+        // Definitions may refer to each other, so resolve those whose references are all resolved
+        // until none are left
+        while !pending.is_empty() {
+            let number_pending = pending.len();
+            let mut unresolved = Vec::new();
+            for (name, mut definition) in pending {
+                match definition.resolve_references(&resolved) {
+                    Ok(()) => {
+                        resolved.insert(name, Arc::new(Bvh::from_root(definition)));
+                    }
+                    Err(missing) => unresolved.push((name, definition, missing)),
+                }
+            }
+
+            if unresolved.len() == number_pending {
+                let (_, _, missing) = unresolved.swap_remove(0);
+                return Err(SceneLoadError::Reference(missing));
+            }
+            pending = unresolved
+                .into_iter()
+                .map(|(name, definition, _)| (name, definition))
+                .collect();
+        }
+
+        self.root
+            .resolve_references(&resolved)
+            .map_err(SceneLoadError::Reference)
+    }
+}
+
+impl TryFrom<SceneDescription> for Scene {
+    type Error = SceneLoadError;
+
+    fn try_from(mut description: SceneDescription) -> Result<Self, Self::Error> {
+        description.resolve_references()?;
+        Ok(Scene::new(
+            description.camera.into(),
+            description.max_ray_depth,
+            Box::new(Bvh::from_root(description.root)),
+            description.background,
+        )
+        .with_lights(description.lights.into_iter().map(Arc::from)))
+    }
+}
+
+/// Parses a scene. Files holding a bare root intersectable, rather than a scene description,
+/// are still accepted.
+pub fn parse_scene(json: &str) -> Result<Scene, SceneLoadError> {
+    let value: serde_json::Value = serde_json::from_str(json)?;
+    let description = if value.get("root").is_some() {
+        SceneDescription::deserialize(value)?
+    } else {
+        SceneDescription::from_root(Box::<dyn Intersectable>::deserialize(value)?)
+    };
+
+    description.try_into()
+}
+
+thread_local! {
+    /// Directory of the scene file being loaded, which relative paths in it are resolved against
+    static SCENE_DIRECTORY: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Resolves a path named in a scene file against the directory of the scene being loaded, or the
+/// working directory when the scene was not loaded from a file.
+pub(crate) fn resolve_path(file_name: &str) -> PathBuf {
+    SCENE_DIRECTORY.with(|directory| match &*directory.borrow() {
+        Some(directory) => directory.join(file_name),
+        None => PathBuf::from(file_name),
+    })
+}
+
+/// A directory for one test to write its files into, unique to the process running it so that
+/// concurrent test runs do not share files, and removed again when dropped.
+#[cfg(test)]
+pub(crate) struct TestDirectory(PathBuf);
+
+#[cfg(test)]
+impl TestDirectory {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "rusty_path_tracer_test_{}_{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn join(&self, file_name: &str) -> PathBuf {
+        self.0.join(file_name)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Loads a scene file. Meshes, textures and environment maps it names are found relative to the
+/// directory holding it.
+pub fn load_scene<P: AsRef<Path>>(file_name: P) -> Result<Scene, SceneLoadError> {
+    let file = fs::read_to_string(&file_name)?;
+    let directory = file_name.as_ref().parent().map(Path::to_path_buf);
+    let previous = SCENE_DIRECTORY.with(|scene_directory| scene_directory.replace(directory));
+    let scene = parse_scene(&file);
+    SCENE_DIRECTORY.with(|scene_directory| scene_directory.replace(previous));
+    scene
+}
+
+#[test]
+pub fn load_scene_from_file() {
+    assert!(load_scene("scene.json").is_ok());
+    assert!(matches!(
+        load_scene("does-not-exist.json"),
+        Err(SceneLoadError::Io(_))
+    ));
+}
+
+#[test]
+pub fn resolve_paths_against_scene_file() {
+    let directory = TestDirectory::new("scene");
+    fs::write(
+        directory.join("triangle.obj"),
+        "v -1 -1 0\nv 1 -1 0\nv 0 1 0\nf 1 2 3\n",
+    )
+    .unwrap();
+    fs::write(
+        directory.join("scene.json"),
+        r#"{ "Mesh": {
+            "file_name": "triangle.obj",
+            "material": { "LightMaterial": { "colour": { "r": 1, "g": 1, "b": 1, "a": 1 } } }
+        } }"#,
+    )
+    .unwrap();
+
+    assert!(load_scene(directory.join("scene.json")).is_ok());
+    assert!(resolve_path("triangle.obj").is_relative());
+}
+
+#[test]
+pub fn parse_scene_description() {
+    use assert_approx_eq::assert_approx_eq;
+
+    let scene = parse_scene(
+        "{
+            \"camera\": {
+                \"position\": { \"x\": 0, \"y\": 10, \"z\": 0 },
+                \"look_at\": { \"x\": 0, \"y\": 0, \"z\": 0 },
+                \"up\": { \"x\": 0, \"y\": 0, \"z\": -1 },
+                \"fov\": 60
+            },
+            \"max_ray_depth\": 3,
+            \"lights\": [{ \"PointLight\": {
+                \"position\": { \"x\": 0, \"y\": 5, \"z\": 0 },
+                \"intensity\": { \"r\": 10, \"g\": 10, \"b\": 10, \"a\": 1 }
+            } }],
+            \"root\": { \"Intersectables\": { \"intersectables\": [] } }
+        }",
+    )
+    .unwrap();
+    assert_eq!(scene.max_ray_depth, 3);
+    assert_eq!(scene.lights.len(), 1);
+    assert_approx_eq!(scene.camera().forward().y, -1.0, 1e-6);
+    assert_approx_eq!(scene.camera().up().z, -1.0, 1e-6);
+
+    let bare_root_scene =
+        parse_scene("{ \"Intersectables\": { \"intersectables\": [] } }").unwrap();
+    assert_eq!(bare_root_scene.max_ray_depth, 16);
+}
+
+#[test]
+pub fn white_furnace() {
+    use crate::intersectable::Intersectables;
+    use crate::material::DiffuseMaterial;
+    use crate::sphere::Sphere;
+
+    // White spheres clustered around the origin, lit evenly from all directions, look exactly as
+    // bright as the sky: light bounces between them as often as it likes, but none is absorbed.
+    // Paths are cut short by Russian roulette, so any bias in that shows up as a departure from
+    // one.
+    let spheres = [
+        Vector3::unit_x(),
+        -Vector3::unit_x(),
+        Vector3::unit_y(),
+        -Vector3::unit_y(),
+        Vector3::unit_z(),
+        -Vector3::unit_z(),
+    ]
+    .into_iter()
+    .map(|direction| -> Box<dyn Intersectable> {
+        Box::new(Sphere {
+            centre: Point3::origin() + direction * 1.5,
+            radius: 1.0,
+            material: Arc::new(DiffuseMaterial {
+                colour: colour::WHITE.into(),
+            }),
+        })
+    })
+    .collect();
+    let scene = Scene::new(
+        Camera::default(),
+        64,
+        Box::new(Intersectables {
+            intersectables: spheres,
+        }),
+        Box::new(SkyBoxMaterial {
+            colour_top: colour::WHITE,
+            colour_bottom: colour::WHITE,
+        }),
+    );
+
+    let mut rng = rand::thread_rng();
+    let samples = 20000;
+    let mut total = 0.0;
+    for _ in 0..samples {
+        let direction = Vector3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let radiance = scene.cast_ray(&Ray::new(Point3::origin(), direction));
+        total += radiance.g;
+    }
+
+    let mean = total / samples as f32;
+    assert!((mean - 1.0).abs() < 0.03, "mean radiance {}", mean);
+}
+
+#[test]
+pub fn emissive_furnace() {
+    use crate::material::{DiffuseMaterial, EmissiveMaterial};
+    use crate::sphere::Sphere;
+
+    // Inside a closed sphere that emits 0.5 and reflects half the light reaching it, every bounce
+    // adds half as much again, so the radiance everywhere converges to one. Paths hit the emitter
+    // both by light sampling and by chance, so any bias in combining the two shows up as a
+    // departure from one.
+    let scene = Scene::new(
+        Camera::default(),
+        64,
+        Box::new(Sphere {
+            centre: Point3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: Arc::new(EmissiveMaterial {
+                colour: colour::WHITE.into(),
+                temperature: None,
+                intensity: 0.5,
+                unit: Default::default(),
+                two_sided: true,
+                material: Some(Arc::new(DiffuseMaterial {
+                    colour: (colour::WHITE * 0.5).into(),
+                })),
+            }),
+        }),
+        Box::new(SkyBoxMaterial {
+            colour_top: colour::BLACK,
+            colour_bottom: colour::BLACK,
+        }),
+    );
+    assert_eq!(scene.lights.len(), 1);
+
+    let mut rng = rand::thread_rng();
+    let samples = 20000;
+    let mut total = 0.0;
+    for _ in 0..samples {
+        let direction = Vector3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let radiance = scene.cast_ray(&Ray::new(Point3::new(0.3, -0.2, 0.1), direction));
+        assert!((radiance.r - radiance.g).abs() < 1e-5);
+        total += radiance.g;
+    }
+
+    let mean = total / samples as f32;
+    assert!((mean - 1.0).abs() < 0.03, "mean radiance {}", mean);
+}
+
+#[test]
+pub fn pick_lights_by_power() {
+    use crate::intersectable::Intersectables;
+    use crate::material::LightMaterial;
+    use crate::sphere::Sphere;
+    use assert_approx_eq::assert_approx_eq;
+
+    // A small bright light gives off ten times the power of a large dim one
+    let light = |x: f32, radius: f32, brightness: f32| -> Box<dyn Intersectable> {
+        Box::new(Sphere {
+            centre: Point3::new(x, 0.0, 0.0),
+            radius,
+            material: Arc::new(LightMaterial {
+                colour: (colour::WHITE * brightness).into(),
+            }),
+        })
+    };
+    let scene = Scene::new(
+        Camera::default(),
+        4,
+        Box::new(Bvh::from_root(Box::new(Intersectables {
+            intersectables: vec![light(-5.0, 1.0, 1.0), light(5.0, 0.1, 1000.0)],
+        }))),
+        default_background(),
+    );
+    let bright = (0..2)
+        .find(|&index| scene.lights[index].power(1.0) > 100.0)
+        .unwrap();
+    assert_approx_eq!(
+        scene.light_distribution.probability(bright),
+        10.0 / 11.0,
+        1e-4
+    );
+
+    // A path hitting the bright light finds it by its material
+    let ray = Ray::new(Point3::new(5.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let hit = scene.root_intersectable.intersect(&ray).unwrap();
+    let (_, pdf) = scene.lights[bright].pdf(&ray).unwrap();
+    assert_approx_eq!(scene.light_pdf(&ray, &hit), pdf * 10.0 / 11.0, 1e-4);
+}
+
+#[test]
+pub fn resolve_references_to_definitions() {
+    let scene_with_definitions = |definitions: &str, root: &str| {
+        parse_scene(&format!(
+            "{{ \"definitions\": {{ {} }}, \"root\": {} }}",
+            definitions, root
+        ))
+    };
+    let sphere = "{ \"Sphere\": {
+        \"centre\": { \"x\": 0, \"y\": 0, \"z\": 0 },
+        \"radius\": 1,
+        \"material\": { \"LightMaterial\": { \"colour\": { \"r\": 1, \"g\": 1, \"b\": 1, \"a\": 1 } } }
+    } }";
+    let moved = |name: &str, x: f32| {
+        format!(
+            "{{ \"Transform\": {{
+                \"translation\": {{ \"x\": {}, \"y\": 0, \"z\": 0 }},
+                \"child\": {{ \"Reference\": {{ \"name\": \"{}\" }} }}
+            }} }}",
+            x, name
+        )
+    };
+
+    // Two instances of a definition that itself refers to another definition
+    let scene = scene_with_definitions(
+        &format!(
+            "\"ball\": {}, \"moved_ball\": {}",
+            sphere,
+            moved("ball", 0.0)
+        ),
+        &format!(
+            "{{ \"Intersectables\": {{ \"intersectables\": [{}, {}] }} }}",
+            moved("moved_ball", -2.0),
+            moved("moved_ball", 2.0)
+        ),
+    )
+    .unwrap();
+    let ray = Ray::new(Point3::new(2.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    assert_eq!(
+        scene.root_intersectable.intersect(&ray).unwrap().distance,
+        4.0
+    );
+    assert_eq!(scene.lights.len(), 2);
+
+    assert!(matches!(
+        scene_with_definitions(&format!("\"ball\": {}", sphere), &moved("cube", 0.0)),
+        Err(SceneLoadError::Reference(name)) if name == "cube"
+    ));
+    assert!(matches!(
+        scene_with_definitions(
+            &format!("\"a\": {}, \"b\": {}", moved("b", 0.0), moved("a", 0.0)),
+            &moved("a", 0.0)
+        ),
+        Err(SceneLoadError::Reference(_))
+    ));
+}
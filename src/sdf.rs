@@ -0,0 +1,388 @@
+use crate::{aabb::Aabb, hit::Hit, intersectable::Intersectable, material::Material, ray::Ray};
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector2, Vector3};
+use serde::{Deserialize, Serialize, Serializer};
+use std::sync::Arc;
+
+/// Most steps taken along a ray before giving up on hitting the surface.
+const MAX_STEPS: usize = 512;
+
+/// How close to the surface a step has to land to count as a hit.
+const HIT_DISTANCE: f32 = 1e-5;
+
+/// Offset used to estimate the gradient of the distance function by finite differences.
+const GRADIENT_OFFSET: f32 = 1e-4;
+
+/// A node in the expression tree of a signed distance function: a primitive shape, or an
+/// operator on the shapes below it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum SdfNode {
+    Sphere {
+        centre: Point3<f32>,
+        radius: f32,
+    },
+    Box {
+        centre: Point3<f32>,
+        half_extent: Vector3<f32>,
+    },
+    /// A ring around the y axis through `centre`, with the tube of `minor_radius` running
+    /// `major_radius` from it.
+    Torus {
+        centre: Point3<f32>,
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    /// All points within `radius` of the line from `start` to `end`.
+    Capsule {
+        start: Point3<f32>,
+        end: Point3<f32>,
+        radius: f32,
+    },
+    /// Everything inside any of the children, blended together where they come within
+    /// `smoothness` of each other.
+    Union {
+        #[serde(default)]
+        smoothness: f32,
+        children: Vec<SdfNode>,
+    },
+    /// What is inside `left` but not inside `right`, with the cut blended over `smoothness`.
+    Subtraction {
+        #[serde(default)]
+        smoothness: f32,
+        left: Box<SdfNode>,
+        right: Box<SdfNode>,
+    },
+    /// Copies of the child every `period` along each axis, without end. Axes with a period of
+    /// zero are not repeated. The child should fit inside the cell around the origin.
+    Repetition {
+        period: Vector3<f32>,
+        child: Box<SdfNode>,
+    },
+    /// The child twisted about the y axis by `rate` for every unit along it.
+    Twist {
+        rate: Deg<f32>,
+        child: Box<SdfNode>,
+    },
+}
+
+impl SdfNode {
+    /// Signed distance from `point` to the surface; negative inside. For twisted shapes this is
+    /// only an estimate, bounded by `lipschitz`.
+    pub fn distance(&self, point: Point3<f32>) -> f32 {
+        match self {
+            SdfNode::Sphere { centre, radius } => (point - centre).magnitude() - radius,
+            SdfNode::Box {
+                centre,
+                half_extent,
+            } => {
+                let q = (point - centre).map(f32::abs) - half_extent;
+                let outside = q.map(|v| v.max(0.0)).magnitude();
+                let inside = q.x.max(q.y).max(q.z).min(0.0);
+                outside + inside
+            }
+            SdfNode::Torus {
+                centre,
+                major_radius,
+                minor_radius,
+            } => {
+                let p = point - centre;
+                let ring = Vector2::new(p.x, p.z).magnitude() - major_radius;
+                Vector2::new(ring, p.y).magnitude() - minor_radius
+            }
+            SdfNode::Capsule { start, end, radius } => {
+                let (along, axis) = (point - start, end - start);
+                let t = (cgmath::dot(along, axis) / axis.magnitude2()).clamp(0.0, 1.0);
+                (along - axis * t).magnitude() - radius
+            }
+            SdfNode::Union {
+                smoothness,
+                children,
+            } => children
+                .iter()
+                .map(|child| child.distance(point))
+                .reduce(|a, b| smooth_min(a, b, *smoothness))
+                .unwrap_or(f32::INFINITY),
+            SdfNode::Subtraction {
+                smoothness,
+                left,
+                right,
+            } => -smooth_min(-left.distance(point), right.distance(point), *smoothness),
+            SdfNode::Repetition { period, child } => {
+                let cell = |v: f32, period: f32| {
+                    if period > 0.0 {
+                        v - period * (v / period).round()
+                    } else {
+                        v
+                    }
+                };
+                child.distance(Point3::new(
+                    cell(point.x, period.x),
+                    cell(point.y, period.y),
+                    cell(point.z, period.z),
+                ))
+            }
+            SdfNode::Twist { rate, child } => {
+                let (sin, cos) = Rad::from(*rate * point.y).0.sin_cos();
+                child.distance(Point3::new(
+                    cos * point.x - sin * point.z,
+                    point.y,
+                    sin * point.x + cos * point.z,
+                ))
+            }
+        }
+    }
+
+    /// Bounds of the shape, or `None` if it goes on without end.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        let around =
+            |centre: Point3<f32>, extent: Vector3<f32>| Aabb::new(centre - extent, centre + extent);
+
+        match self {
+            SdfNode::Sphere { centre, radius } => {
+                Some(around(*centre, Vector3::new(*radius, *radius, *radius)))
+            }
+            SdfNode::Box {
+                centre,
+                half_extent,
+            } => Some(around(*centre, *half_extent)),
+            SdfNode::Torus {
+                centre,
+                major_radius,
+                minor_radius,
+            } => {
+                let outer = major_radius + minor_radius;
+                Some(around(*centre, Vector3::new(outer, *minor_radius, outer)))
+            }
+            SdfNode::Capsule { start, end, radius } => {
+                let extent = Vector3::new(*radius, *radius, *radius);
+                Some(around(*start, extent).union(&around(*end, extent)))
+            }
+            SdfNode::Union {
+                smoothness,
+                children,
+            } => {
+                // Blending pulls the surface out by at most a quarter of the smoothness
+                let blend = smoothness.max(0.0) * 0.25;
+                let bounds = children
+                    .iter()
+                    .map(SdfNode::bounding_box)
+                    .try_fold(Aabb::empty(), |bounds, child| Some(bounds.union(&child?)))?;
+                Some(Aabb::new(
+                    bounds.min - Vector3::new(blend, blend, blend),
+                    bounds.max + Vector3::new(blend, blend, blend),
+                ))
+            }
+            SdfNode::Subtraction { left, .. } => left.bounding_box(),
+            SdfNode::Repetition { .. } => None,
+            SdfNode::Twist { child, .. } => {
+                let bounds = child.bounding_box()?;
+                let radius = twist_radius(&bounds);
+                Some(Aabb::new(
+                    Point3::new(-radius, bounds.min.y, -radius),
+                    Point3::new(radius, bounds.max.y, radius),
+                ))
+            }
+        }
+    }
+
+    /// Upper bound on how fast `distance` changes with position. Steps along a ray are divided
+    /// by this so they never overshoot the surface.
+    pub fn lipschitz(&self) -> f32 {
+        match self {
+            SdfNode::Union { children, .. } => {
+                children.iter().map(SdfNode::lipschitz).fold(1.0, f32::max)
+            }
+            SdfNode::Subtraction { left, right, .. } => left.lipschitz().max(right.lipschitz()),
+            SdfNode::Repetition { child, .. } => child.lipschitz(),
+            SdfNode::Twist { rate, child } => {
+                let radius = child
+                    .bounding_box()
+                    .map_or(1.0, |bounds| twist_radius(&bounds));
+                let stretch = Rad::from(*rate).0 * radius;
+                child.lipschitz() * (1.0 + stretch * stretch).sqrt()
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+/// Minimum of `a` and `b`, rounded off where they are within `smoothness` of each other.
+fn smooth_min(a: f32, b: f32, smoothness: f32) -> f32 {
+    if smoothness <= 0.0 {
+        return a.min(b);
+    }
+
+    let h = (0.5 + 0.5 * (b - a) / smoothness).clamp(0.0, 1.0);
+    b + (a - b) * h - smoothness * h * (1.0 - h)
+}
+
+/// Furthest distance from the y axis of any point in `bounds`.
+fn twist_radius(bounds: &Aabb) -> f32 {
+    bounds
+        .corners()
+        .iter()
+        .map(|corner| Vector2::new(corner.x, corner.z).magnitude())
+        .fold(0.0, f32::max)
+}
+
+/// How a signed distance field shape is described in the scene file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SdfDescription {
+    pub shape: SdfNode,
+    #[serde(default = "default_max_distance")]
+    pub max_distance: f32,
+    pub material: Arc<dyn Material>,
+}
+
+fn default_max_distance() -> f32 {
+    1000.0
+}
+
+/// A shape given by a signed distance function, intersected by sphere tracing: stepping along the
+/// ray by the distance to the nearest surface until it is close enough to count as a hit.
+///
+/// Shapes that repeat without end are marched out to `max_distance`.
+///
+/// Emissive materials light the scene only where paths bounce into them, as there is no way to pick
+/// points on the surface for sampling it directly as a light; small bright shapes converge slowly.
+#[derive(Debug, Deserialize)]
+#[serde(from = "SdfDescription")]
+pub struct Sdf {
+    description: SdfDescription,
+    /// Bounds of the shape, worked out once from the expression tree
+    bounds: Option<Aabb>,
+    /// Lipschitz bound of the distance function, worked out once from the expression tree
+    lipschitz: f32,
+}
+
+impl From<SdfDescription> for Sdf {
+    fn from(description: SdfDescription) -> Self {
+        Self {
+            bounds: description.shape.bounding_box(),
+            lipschitz: description.shape.lipschitz(),
+            description,
+        }
+    }
+}
+
+impl Serialize for Sdf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.description.serialize(serializer)
+    }
+}
+
+impl Sdf {
+    /// Outward surface normal at `point`, from the gradient of the distance function.
+    fn normal(&self, point: Point3<f32>) -> Vector3<f32> {
+        // Central differences along the four corners of a tetrahedron
+        let gradient = [
+            Vector3::new(1.0, -1.0, -1.0),
+            Vector3::new(-1.0, -1.0, 1.0),
+            Vector3::new(-1.0, 1.0, -1.0),
+            Vector3::new(1.0, 1.0, 1.0),
+        ]
+        .iter()
+        .fold(Vector3::new(0.0, 0.0, 0.0), |gradient, corner| {
+            gradient
+                + corner
+                    * self
+                        .description
+                        .shape
+                        .distance(point + corner * GRADIENT_OFFSET)
+        });
+        gradient.normalize()
+    }
+}
+
+#[typetag::serde]
+impl Intersectable for Sdf {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let (near, far) = match self.bounds {
+            Some(bounds) => bounds.crossing(ray)?,
+            None => (0.0, self.description.max_distance),
+        };
+        if far < 0.0 {
+            return None;
+        }
+
+        // March on the distance to the surface from whichever side the ray starts on
+        let mut distance = near.max(0.0);
+        let side = self
+            .description
+            .shape
+            .distance(ray.origin + ray.direction * distance)
+            .signum();
+        for _ in 0..MAX_STEPS {
+            let point = ray.origin + ray.direction * distance;
+            let step = side * self.description.shape.distance(point) / self.lipschitz;
+            if step < HIT_DISTANCE {
+                return Some(Hit::new(
+                    ray,
+                    distance,
+                    self.normal(point),
+                    self.description.material.clone(),
+                ));
+            }
+
+            distance += step;
+            if distance > far {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds
+    }
+}
+
+#[test]
+pub fn sphere_trace_shapes() {
+    use crate::{colour, material::LightMaterial};
+    use assert_approx_eq::assert_approx_eq;
+    use cgmath::EuclideanSpace;
+
+    let sdf = |shape: &str| -> Sdf {
+        Sdf::from(SdfDescription {
+            shape: serde_json::from_str(shape).unwrap(),
+            max_distance: default_max_distance(),
+            material: Arc::new(LightMaterial {
+                colour: colour::WHITE.into(),
+            }),
+        })
+    };
+    let down = Ray::new(Point3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+
+    let sphere =
+        sdf("{ \"Sphere\": { \"centre\": { \"x\": 0, \"y\": 0, \"z\": 0 }, \"radius\": 1 } }");
+    let hit = sphere.intersect(&down).unwrap();
+    assert_approx_eq!(hit.distance, 4.0, 1e-4);
+    assert_approx_eq!(hit.normal.y, 1.0, 1e-3);
+
+    // From inside, the ray leaves through the far side
+    let inside = sphere
+        .intersect(&Ray::new(Point3::origin(), Vector3::unit_x()))
+        .unwrap();
+    assert_approx_eq!(inside.distance, 1.0, 1e-4);
+    assert!(!inside.front_face);
+
+    // The hole of a torus lets the ray through
+    let torus = sdf(
+        "{ \"Torus\": { \"centre\": { \"x\": 0, \"y\": 0, \"z\": 0 }, \"major_radius\": 2, \"minor_radius\": 0.5 } }",
+    );
+    assert!(torus.intersect(&down).is_none());
+    let offset = Ray::new(Point3::new(2.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+    assert_approx_eq!(torus.intersect(&offset).unwrap().distance, 4.5, 1e-4);
+
+    // Spheres repeated along x, marched to without any bounds
+    let row = sdf(
+        "{ \"Repetition\": {
+            \"period\": { \"x\": 4, \"y\": 0, \"z\": 0 },
+            \"child\": { \"Sphere\": { \"centre\": { \"x\": 0, \"y\": 0, \"z\": 0 }, \"radius\": 1 } }
+        } }",
+    );
+    assert!(row.bounding_box().is_none());
+    let shifted = Ray::new(Point3::new(40.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+    assert_approx_eq!(row.intersect(&shifted).unwrap().distance, 4.0, 1e-4);
+}
@@ -0,0 +1,471 @@
+use crate::{
+    colour::{xyz_to_linear_srgb, Colour, BLACK},
+    hit::Hit,
+    intersectable::orthonormal_basis,
+    light::{unlisted_light, Light, LightSample},
+    material::Material,
+    ray::Ray,
+    tone_mapping::luminance,
+};
+use cgmath::{Deg, InnerSpace, Point3, Rad, Vector3};
+use rand::Rng;
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+use std::{f32::consts::PI, fmt, sync::Arc};
+
+/// Illuminance of the sun above the atmosphere, in thousands of lux.
+const SOLAR_ILLUMINANCE: f32 = 128.0;
+/// Wavelengths in micrometres at which the red, green and blue attenuation of sunlight is taken.
+const WAVELENGTHS: [f32; 3] = [0.68, 0.55, 0.44];
+/// Steps in polar angle over which sky light falling on the ground is summed; twice as many are
+/// taken around the horizon.
+const IRRADIANCE_STEPS: usize = 32;
+
+/// How a daylight sky is described in the scene file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SkyDescription {
+    /// Direction towards the sun, which lights the scene only while above the horizon
+    pub sun_direction: Vector3<f32>,
+    /// Haziness of the air, from 2 for a very clear sky to 10 for a hazy one
+    #[serde(default = "default_turbidity")]
+    pub turbidity: f32,
+    /// Reflectance of the ground below the horizon
+    #[serde(default = "default_ground_albedo")]
+    pub ground_albedo: Colour,
+    /// Apparent radius of the sun. Larger suns give softer shadows, without lighting the scene
+    /// any brighter.
+    #[serde(default = "default_sun_angular_radius")]
+    pub sun_angular_radius: Deg<f32>,
+    /// Scales all radiance, which is otherwise in thousands of candela per square metre. The
+    /// default brings white surfaces in midday sun to about one.
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+}
+
+fn default_turbidity() -> f32 {
+    3.0
+}
+
+fn default_ground_albedo() -> Colour {
+    Colour {
+        r: 0.3,
+        g: 0.3,
+        b: 0.3,
+        a: 1.0,
+    }
+}
+
+fn default_sun_angular_radius() -> Deg<f32> {
+    Deg(0.2665)
+}
+
+fn default_intensity() -> f32 {
+    0.03
+}
+
+/// The five coefficients of the Perez sky luminance distribution.
+#[derive(Debug, Clone, Copy)]
+struct Perez([f32; 5]);
+
+impl Perez {
+    /// Coefficients depending linearly on turbidity, as `(slope, intercept)` pairs.
+    fn new(turbidity: f32, coefficients: [(f32, f32); 5]) -> Self {
+        Self(coefficients.map(|(slope, intercept)| slope * turbidity + intercept))
+    }
+
+    /// Relative brightness at angle `theta` from the zenith and `gamma` from the sun.
+    fn evaluate(&self, theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = self.0;
+        let cos_theta = theta.cos().max(1e-3);
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+}
+
+/// The clear sky model of Preetham, Shirley and Smits, "A Practical Analytic Model for
+/// Daylight", 1999, with a sun disk that can be sampled as a light.
+pub struct Sky {
+    sun_direction: Vector3<f32>,
+    /// Angle of the sun from the zenith, held at the horizon once the sun has set
+    sun_theta: f32,
+    /// Cosine of the angular radius of the sun
+    cos_sun_radius: f32,
+    /// Radiance of the sun disk, or black when the sun is below the horizon
+    sun_radiance: Colour,
+    /// Luminance and chromaticity at the zenith
+    zenith: [f32; 3],
+    /// Distributions of luminance and of the two chromaticity coordinates
+    distributions: [Perez; 3],
+    /// Radiance of the ground, lit by sun and sky
+    ground_radiance: Colour,
+    intensity: f32,
+}
+
+impl Sky {
+    pub fn new(description: &SkyDescription) -> Self {
+        let turbidity = description.turbidity.clamp(1.7, 10.0);
+        let sun_direction = description.sun_direction.normalize();
+        let sun_theta = sun_direction.y.clamp(0.0, 1.0).acos();
+        let cos_sun_radius = Rad::from(description.sun_angular_radius).0.cos();
+
+        let distributions = [
+            Perez::new(
+                turbidity,
+                [
+                    (0.1787, -1.4630),
+                    (-0.3554, 0.4275),
+                    (-0.0227, 5.3251),
+                    (0.1206, -2.5771),
+                    (-0.0670, 0.3703),
+                ],
+            ),
+            Perez::new(
+                turbidity,
+                [
+                    (-0.0193, -0.2592),
+                    (-0.0665, 0.0008),
+                    (-0.0004, 0.2125),
+                    (-0.0641, -0.8989),
+                    (-0.0033, 0.0452),
+                ],
+            ),
+            Perez::new(
+                turbidity,
+                [
+                    (-0.0167, -0.2608),
+                    (-0.0950, 0.0092),
+                    (-0.0079, 0.2102),
+                    (-0.0441, -1.6537),
+                    (-0.0109, 0.0529),
+                ],
+            ),
+        ];
+
+        let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * sun_theta);
+        let zenith_luminance =
+            ((4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192).max(0.0);
+        let zenith_chromaticity = |coefficients: [[f32; 4]; 3]| {
+            let powers = [sun_theta.powi(3), sun_theta.powi(2), sun_theta, 1.0];
+            let polynomial =
+                |row: [f32; 4]| -> f32 { row.iter().zip(powers).map(|(c, power)| c * power).sum() };
+            turbidity * turbidity * polynomial(coefficients[0])
+                + turbidity * polynomial(coefficients[1])
+                + polynomial(coefficients[2])
+        };
+        let zenith = [
+            zenith_luminance,
+            zenith_chromaticity([
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ]),
+            zenith_chromaticity([
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ]),
+        ];
+
+        // Sunlight is thinned out by scattering off air molecules and off haze, more so at short
+        // wavelengths and through the thicker air towards the horizon
+        let sun_above_horizon = sun_direction.y > 0.0;
+        let sun_solid_angle = 2.0 * PI * (1.0 - cos_sun_radius);
+        let sun_radiance = if sun_above_horizon {
+            let relative_air_mass =
+                1.0 / (sun_theta.cos() + 0.15 * (93.885 - sun_theta.to_degrees()).powf(-1.253));
+            let haze = 0.04608 * turbidity - 0.04586;
+            let [r, g, b] = WAVELENGTHS.map(|wavelength| {
+                let rayleigh = 0.008735 * wavelength.powf(-4.08);
+                let aerosol = haze * wavelength.powf(-1.3);
+                (-(rayleigh + aerosol) * relative_air_mass).exp()
+            });
+            Colour { r, g, b, a: 1.0 } * (SOLAR_ILLUMINANCE / sun_solid_angle)
+        } else {
+            BLACK
+        };
+
+        let mut sky = Self {
+            sun_direction,
+            sun_theta,
+            cos_sun_radius,
+            sun_radiance,
+            zenith,
+            distributions,
+            ground_radiance: BLACK,
+            intensity: description.intensity,
+        };
+
+        // The ground reflects evenly all the light falling on it from the sun and sky
+        let sun_irradiance = sun_radiance * (sun_solid_angle * sun_direction.y.max(0.0));
+        let (d_theta, d_phi) = (
+            0.5 * PI / IRRADIANCE_STEPS as f32,
+            PI / IRRADIANCE_STEPS as f32,
+        );
+        let mut sky_irradiance = BLACK;
+        for i in 0..IRRADIANCE_STEPS {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..2 * IRRADIANCE_STEPS {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let direction = Vector3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                sky_irradiance = sky_irradiance
+                    + sky.sky_radiance(&direction) * (theta.cos() * theta.sin() * d_theta * d_phi);
+            }
+        }
+        sky.ground_radiance =
+            description.ground_albedo * (sun_irradiance + sky_irradiance) * (1.0 / PI);
+        sky
+    }
+
+    /// Radiance of the sky alone, without the sun disk, arriving from `direction` above the
+    /// horizon.
+    fn sky_radiance(&self, direction: &Vector3<f32>) -> Colour {
+        let theta = direction.y.clamp(0.0, 1.0).acos();
+        let gamma = cgmath::dot(*direction, self.sun_direction)
+            .clamp(-1.0, 1.0)
+            .acos();
+        let [luminance, x, y] = [0, 1, 2].map(|index| {
+            let distribution = self.distributions[index];
+            self.zenith[index] * distribution.evaluate(theta, gamma)
+                / distribution.evaluate(0.0, self.sun_theta)
+        });
+        xyy_to_linear_srgb(x, y, luminance.max(0.0))
+    }
+
+    /// Radiance arriving from `direction`, including the sun when looking straight at it.
+    pub fn radiance(&self, direction: &Vector3<f32>) -> Colour {
+        let direction = direction.normalize();
+        let radiance = if direction.y < 0.0 {
+            self.ground_radiance
+        } else if cgmath::dot(direction, self.sun_direction) >= self.cos_sun_radius {
+            self.sky_radiance(&direction) + self.sun_radiance
+        } else {
+            self.sky_radiance(&direction)
+        };
+        radiance * self.intensity
+    }
+
+    fn is_sun_up(&self) -> bool {
+        self.sun_direction.y > 0.0
+    }
+
+    /// Solid angle density of sampling a direction on the sun disk.
+    fn sun_pdf(&self) -> f32 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+    }
+}
+
+/// Converts CIE xyY chromaticity and luminance to linear sRGB.
+fn xyy_to_linear_srgb(x: f32, y: f32, luminance: f32) -> Colour {
+    if y <= 0.0 {
+        return BLACK;
+    }
+
+    xyz_to_linear_srgb(x * luminance / y, luminance, (1.0 - x - y) * luminance / y)
+}
+
+impl fmt::Debug for Sky {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sky")
+            .field("sun_direction", &self.sun_direction)
+            .field("sun_radiance", &self.sun_radiance)
+            .finish()
+    }
+}
+
+impl Serialize for Sky {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom(unlisted_light(
+            "Sky",
+            "a SkyMaterial background",
+        )))
+    }
+}
+
+impl<'de> Deserialize<'de> for Sky {
+    fn deserialize<D: Deserializer<'de>>(_deserializer: D) -> Result<Self, D::Error> {
+        Err(de::Error::custom(unlisted_light(
+            "Sky",
+            "a SkyMaterial background",
+        )))
+    }
+}
+
+/// The sun disk, sampled evenly over the cone of directions it covers, less any part of it below
+/// the horizon.
+#[typetag::serde]
+impl Light for Sky {
+    fn sample(&self, _position: &Point3<f32>) -> Option<LightSample> {
+        if !self.is_sun_up() {
+            return None;
+        }
+
+        let mut rng = rand::thread_rng();
+        let cos_theta = 1.0 - rng.gen::<f32>() * (1.0 - self.cos_sun_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f32>();
+        let (tangent, bitangent) = orthonormal_basis(self.sun_direction, None);
+        let direction = (tangent * (sin_theta * phi.cos())
+            + bitangent * (sin_theta * phi.sin())
+            + self.sun_direction * cos_theta)
+            .normalize();
+
+        // Below the horizon the ground hides the sun
+        if direction.y < 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance: f32::INFINITY,
+            radiance: self.radiance(&direction),
+            pdf: self.sun_pdf(),
+        })
+    }
+
+    fn pdf(&self, ray: &Ray) -> Option<(f32, f32)> {
+        let direction = ray.direction.normalize();
+        let on_sun = cgmath::dot(direction, self.sun_direction) >= self.cos_sun_radius;
+        if !self.is_sun_up() || !on_sun || direction.y < 0.0 {
+            return None;
+        }
+
+        Some((f32::INFINITY, self.sun_pdf()))
+    }
+
+    fn power(&self, scene_radius: f32) -> f32 {
+        if !self.is_sun_up() {
+            return 0.0;
+        }
+
+        let sun_irradiance = luminance(&self.sun_radiance) * self.intensity / self.sun_pdf();
+        PI * scene_radius * scene_radius * sun_irradiance
+    }
+}
+
+/// A daylight sky background, whose sun is added to the lights of the scene.
+#[derive(Deserialize)]
+#[serde(try_from = "SkyDescription")]
+pub struct SkyMaterial {
+    description: SkyDescription,
+    sky: Arc<Sky>,
+}
+
+impl fmt::Debug for SkyMaterial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SkyMaterial")
+            .field("description", &self.description)
+            .field("sky", &self.sky)
+            .finish()
+    }
+}
+
+impl TryFrom<SkyDescription> for SkyMaterial {
+    type Error = String;
+
+    fn try_from(description: SkyDescription) -> Result<Self, Self::Error> {
+        let length = description.sun_direction.magnitude();
+        if !length.is_finite() || length == 0.0 {
+            return Err("sky sun_direction must be a nonzero vector".to_string());
+        }
+
+        Ok(Self {
+            sky: Arc::new(Sky::new(&description)),
+            description,
+        })
+    }
+}
+
+impl Serialize for SkyMaterial {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.description.serialize(serializer)
+    }
+}
+
+#[typetag::serde]
+impl Material for SkyMaterial {
+    fn emitted(&self, view_direction: &Vector3<f32>, _hit: &Hit) -> Colour {
+        self.sky.radiance(view_direction)
+    }
+
+    fn background_light(&self) -> Option<Arc<dyn Light>> {
+        Some(self.sky.clone())
+    }
+}
+
+#[test]
+pub fn evaluate_sky() {
+    use assert_approx_eq::assert_approx_eq;
+
+    let sky = |sun_direction: Vector3<f32>| {
+        Sky::new(&SkyDescription {
+            sun_direction,
+            turbidity: default_turbidity(),
+            ground_albedo: default_ground_albedo(),
+            sun_angular_radius: default_sun_angular_radius(),
+            intensity: 1.0,
+        })
+    };
+
+    // A clear midday sky is blue overhead and brighter towards the sun
+    let noon = sky(Vector3::new(0.0, 1.0, 0.5));
+    let overhead = noon.radiance(&Vector3::unit_y());
+    assert!(overhead.b > overhead.r);
+    assert!(
+        noon.radiance(&Vector3::new(0.0, 0.5, 1.0)).g
+            > noon.radiance(&Vector3::new(0.0, 0.5, -1.0)).g
+    );
+    assert!(noon.radiance(&Vector3::new(1.0, -1.0, 0.0)).g > 0.0);
+
+    // Sampled directions lie on the sun, and light sampling agrees about them
+    let origin = Point3::new(0.0, 0.0, 0.0);
+    for _ in 0..16 {
+        let sample = noon.sample(&origin).unwrap();
+        assert!(cgmath::dot(sample.direction, noon.sun_direction) >= noon.cos_sun_radius);
+        let (distance, pdf) = noon.pdf(&Ray::new(origin, sample.direction)).unwrap();
+        assert!(distance.is_infinite());
+        assert_approx_eq!(pdf, sample.pdf, sample.pdf * 1e-3);
+        assert!(sample.radiance.r > noon.radiance(&Vector3::unit_y()).r);
+    }
+    assert!(noon.pdf(&Ray::new(origin, Vector3::unit_x())).is_none());
+
+    // The setting sun is redder, and once set lights nothing
+    let sunset = sky(Vector3::new(0.0, 0.05, 1.0));
+    assert!(
+        sunset.sun_radiance.r / sunset.sun_radiance.b > noon.sun_radiance.r / noon.sun_radiance.b
+    );
+    assert!(sky(Vector3::new(0.0, -0.2, 1.0)).sample(&origin).is_none());
+
+    // Half set, only the part of the disk above the horizon is sampled or hit
+    let setting = Sky::new(&SkyDescription {
+        sun_direction: Vector3::new(0.0, 0.01, 1.0),
+        turbidity: default_turbidity(),
+        ground_albedo: default_ground_albedo(),
+        sun_angular_radius: Deg(5.0),
+        intensity: 1.0,
+    });
+    let samples: Vec<_> = (0..200).filter_map(|_| setting.sample(&origin)).collect();
+    assert!(!samples.is_empty() && samples.len() < 200);
+    assert!(samples.iter().all(|sample| sample.direction.y >= 0.0));
+    let below = Ray::new(origin, Vector3::new(0.0, -0.02, 1.0));
+    assert!(setting.pdf(&below).is_none());
+    assert!(setting
+        .pdf(&Ray::new(origin, Vector3::new(0.0, 0.02, 1.0)))
+        .is_some());
+
+    let error = serde_json::from_str::<Box<dyn Material>>(
+        "{ \"SkyMaterial\": { \"sun_direction\": { \"x\": 0, \"y\": 0, \"z\": 0 } } }",
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("nonzero"));
+
+    let material: Box<dyn Material> = serde_json::from_str(
+        "{ \"SkyMaterial\": { \"sun_direction\": { \"x\": 1, \"y\": 1, \"z\": 0 }, \"turbidity\": 4 } }",
+    )
+    .unwrap();
+    assert!(material.background_light().is_some());
+    assert!(serde_json::to_string(&material)
+        .unwrap()
+        .contains("\"turbidity\":4.0"));
+}
@@ -1,9 +1,18 @@
-use crate::{hit::Hit, intersectable::Intersectable, ray::Ray, Material};
-use cgmath::{InnerSpace, Point3};
+use crate::{
+    aabb::Aabb,
+    hit::Hit,
+    intersectable::{Intersectable, Interval},
+    light::{Emitter, SurfaceSample},
+    material::Material,
+    ray::Ray,
+};
+use cgmath::{Point3, Vector2, Vector3};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
 use std::sync::Arc;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Sphere {
     pub centre: Point3<f32>,
     pub radius: f32,
@@ -26,19 +35,119 @@ impl Intersectable for Sphere {
             return None;
         }
 
-        let distance = -b - discriminant.sqrt();
+        // Use the far intersection if the ray starts inside the sphere
+        let discriminant_root = discriminant.sqrt();
+        let distance = if -b - discriminant_root >= 0.0 {
+            -b - discriminant_root
+        } else {
+            -b + discriminant_root
+        };
         if distance < 0.0 {
             return None;
         }
 
+        Some(self.hit_at(ray, distance))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        let m = ray.origin - self.centre;
+        let b = cgmath::dot(m, ray.direction);
+        let c = cgmath::dot(m, m) - self.radius * self.radius;
+
+        let discriminant = (b * b) - c;
+        if discriminant < 0.0 {
+            return Some(Vec::new());
+        }
+
+        let discriminant_root = discriminant.sqrt();
+        Some(vec![Interval {
+            entry: self.hit_at(ray, -b - discriminant_root),
+            exit: self.hit_at(ray, -b + discriminant_root),
+        }])
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = Vector3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.centre - extent, self.centre + extent))
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        if self.material.is_emissive() {
+            emitters.push(Arc::new(self.clone()));
+        }
+    }
+}
+
+impl Sphere {
+    fn hit_at(&self, ray: &Ray, distance: f32) -> Hit {
         let intersection_point = ray.origin + (distance * ray.direction);
-        let normal = (intersection_point - self.centre).normalize();
+        let normal = (intersection_point - self.centre) / self.radius;
+
+        // Longitude around the y axis, starting from +z, and latitude from the south pole
+        let uv = Vector2::new(
+            0.5 + normal.x.atan2(normal.z) / (2.0 * PI),
+            0.5 + normal.y.clamp(-1.0, 1.0).asin() / PI,
+        );
 
-        Some(Hit::new(
-            distance,
-            intersection_point,
+        Hit::new(ray, distance, normal, self.material.clone())
+            .with_uv(uv)
+            .with_tangent(Vector3::new(normal.z, 0.0, -normal.x))
+    }
+}
+
+impl Emitter for Sphere {
+    fn sample_surface(&self) -> SurfaceSample {
+        let mut rng = rand::thread_rng();
+        let z = 1.0 - 2.0 * rng.gen::<f32>();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f32>();
+        let normal = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+
+        SurfaceSample {
+            position: self.centre + normal * self.radius,
             normal,
-            self.material.clone(),
-        ))
+            pdf: 1.0 / self.area(),
+        }
+    }
+
+    fn surface_pdf(&self, _hit: &Hit) -> f32 {
+        1.0 / self.area()
+    }
+
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit> {
+        self.intersect(ray)
     }
+
+    fn area(&self) -> f32 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        &self.material
+    }
+}
+
+#[test]
+pub fn intersect_sphere_from_inside() {
+    use crate::{colour, material::LightMaterial};
+
+    let sphere = Sphere {
+        centre: Point3::new(0.0, 0.0, 0.0),
+        radius: 2.0,
+        material: Arc::new(LightMaterial {
+            colour: colour::WHITE.into(),
+        }),
+    };
+
+    let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let hit = sphere.intersect(&ray).unwrap();
+    assert_eq!(hit.distance, 3.0);
+    assert!(hit.front_face);
+    assert_eq!(hit.normal, Vector3::new(0.0, 0.0, 1.0));
+
+    let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+    let hit = sphere.intersect(&ray).unwrap();
+    assert_eq!(hit.distance, 3.0);
+    assert!(!hit.front_face);
+    assert_eq!(hit.normal, Vector3::new(0.0, 0.0, 1.0));
 }
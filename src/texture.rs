@@ -0,0 +1,447 @@
+use crate::{colour::Colour, hit::Hit, scene::resolve_path, tone_mapping::srgb_to_linear};
+use cgmath::{Vector2, VectorSpace};
+use image::DynamicImage;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, fmt::Debug, sync::Arc};
+
+/// A colour that varies over surfaces.
+#[typetag::serde]
+pub trait Texture: Debug + Send + Sync {
+    /// Linear colour of the texture at the hit.
+    fn colour(&self, hit: &Hit) -> Colour;
+
+    /// Value of the texture at the hit, for scalar parameters such as roughness; by default the
+    /// average of the colour channels.
+    fn value(&self, hit: &Hit) -> f32 {
+        let colour = self.colour(hit);
+        (colour.r + colour.g + colour.b) / 3.0
+    }
+
+    /// Rough average colour over the whole texture, for how bright it is overall.
+    fn average(&self) -> Colour;
+}
+
+/// A colour parameter of a material, given in the scene file either as a plain colour or as a
+/// texture.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ColourSource {
+    Constant(Colour),
+    Texture(Arc<dyn Texture>),
+}
+
+impl ColourSource {
+    pub fn colour(&self, hit: &Hit) -> Colour {
+        match self {
+            ColourSource::Constant(colour) => *colour,
+            ColourSource::Texture(texture) => texture.colour(hit),
+        }
+    }
+
+    /// Rough average of the colour over all surfaces.
+    pub fn average(&self) -> Colour {
+        match self {
+            ColourSource::Constant(colour) => *colour,
+            ColourSource::Texture(texture) => texture.average(),
+        }
+    }
+}
+
+/// Anything that does not parse as a colour is taken to be a texture, so that errors in the
+/// texture, such as a missing image, are reported as they are.
+impl<'de> Deserialize<'de> for ColourSource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        if let Ok(colour) = Colour::deserialize(&value) {
+            return Ok(ColourSource::Constant(colour));
+        }
+        deserialize_texture(value).map(ColourSource::Texture)
+    }
+}
+
+impl From<Colour> for ColourSource {
+    fn from(colour: Colour) -> Self {
+        ColourSource::Constant(colour)
+    }
+}
+
+/// A scalar parameter of a material, given in the scene file either as a plain number or as a
+/// texture, whose channels are averaged.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum ScalarSource {
+    Constant(f32),
+    Texture(Arc<dyn Texture>),
+}
+
+impl ScalarSource {
+    pub fn value(&self, hit: &Hit) -> f32 {
+        match self {
+            ScalarSource::Constant(value) => *value,
+            ScalarSource::Texture(texture) => texture.value(hit),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ScalarSource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        if let Some(number) = value.as_f64() {
+            return Ok(ScalarSource::Constant(number as f32));
+        }
+        deserialize_texture(value).map(ScalarSource::Texture)
+    }
+}
+
+/// Deserializes a texture from `value`, passing on its error if it is not one.
+fn deserialize_texture<E: de::Error>(value: serde_json::Value) -> Result<Arc<dyn Texture>, E> {
+    Box::<dyn Texture>::deserialize(value)
+        .map(Arc::from)
+        .map_err(E::custom)
+}
+
+impl From<f32> for ScalarSource {
+    fn from(value: f32) -> Self {
+        ScalarSource::Constant(value)
+    }
+}
+
+impl Default for ScalarSource {
+    fn default() -> Self {
+        ScalarSource::Constant(0.0)
+    }
+}
+
+/// How texture coordinates outside the range 0 to 1 are brought back onto the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum WrapMode {
+    /// Tile the image
+    #[default]
+    Repeat,
+    /// Tile the image, flipping every other copy so the edges meet seamlessly
+    Mirror,
+    /// Stretch the edge pixels outwards
+    Clamp,
+}
+
+impl WrapMode {
+    /// Brings the pixel coordinate `coordinate` into an image `size` pixels across.
+    fn apply(self, coordinate: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => coordinate.rem_euclid(size),
+            WrapMode::Mirror => {
+                let period = coordinate.rem_euclid(2 * size);
+                if period < size {
+                    period
+                } else {
+                    2 * size - 1 - period
+                }
+            }
+            WrapMode::Clamp => coordinate.clamp(0, size - 1),
+        };
+        wrapped as usize
+    }
+}
+
+/// How the channels of an 8 or 16 bit image are encoded. Floating point images are always linear.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ColourEncoding {
+    Srgb,
+    Linear,
+}
+
+/// How an image texture is described in the scene file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ImageTextureDescription {
+    /// Path to a PNG, JPEG, HDR or EXR image
+    pub file_name: String,
+    #[serde(default)]
+    pub wrap: WrapMode,
+    /// Encoding of the image. If not given, colours are taken to be sRGB encoded, and scalar
+    /// parameters, such as roughness maps, to be linear.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<ColourEncoding>,
+}
+
+/// Loads the channels of the image in `file_name`, relative to the scene file, as they are
+/// stored, in rows from the top. Also gives whether they are floating point, and so linear.
+fn load_channels(file_name: &str) -> image::ImageResult<(usize, usize, Vec<Colour>, bool)> {
+    let image = image::open(resolve_path(file_name))?;
+    let is_float = matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+
+    let image = image.to_rgb32f();
+    let pixels = image
+        .pixels()
+        .map(|pixel| Colour {
+            r: pixel[0],
+            g: pixel[1],
+            b: pixel[2],
+            a: 1.0,
+        })
+        .collect();
+    Ok((
+        image.width() as usize,
+        image.height() as usize,
+        pixels,
+        is_float,
+    ))
+}
+
+/// Decodes the channels of an sRGB encoded colour.
+fn decode_srgb(colour: Colour) -> Colour {
+    Colour {
+        r: srgb_to_linear(colour.r),
+        g: srgb_to_linear(colour.g),
+        b: srgb_to_linear(colour.b),
+        a: colour.a,
+    }
+}
+
+/// Loads the image in `file_name`, relative to the scene file, as its width, height and linear
+/// colours in rows from the top. Floating point images are taken to be linear, others to be sRGB
+/// encoded.
+pub(crate) fn load_image(file_name: &str) -> image::ImageResult<(usize, usize, Vec<Colour>)> {
+    let (width, height, pixels, is_float) = load_channels(file_name)?;
+    let pixels = if is_float {
+        pixels
+    } else {
+        pixels.into_iter().map(decode_srgb).collect()
+    };
+    Ok((width, height, pixels))
+}
+
+/// An image laid over the UV coordinates of surfaces, with `u` running left to right and `v`
+/// bottom to top, and filtered bilinearly between pixels.
+#[derive(Deserialize)]
+#[serde(try_from = "ImageTextureDescription")]
+pub struct ImageTexture {
+    description: ImageTextureDescription,
+    width: usize,
+    height: usize,
+    /// Linear colours, in rows from the top
+    pixels: Vec<Colour>,
+    /// Averaged channels for scalar parameters, decoded only if the image says it is sRGB
+    values: Vec<f32>,
+    /// Mean of `pixels`
+    average: Colour,
+}
+
+impl ImageTexture {
+    pub fn load(description: ImageTextureDescription) -> Result<Self, String> {
+        let (width, height, channels, is_float) =
+            load_channels(&description.file_name).map_err(|error| {
+                format!(
+                    "failed to load texture '{}': {}",
+                    description.file_name, error
+                )
+            })?;
+
+        let decode = |pixel: &Colour, encoding| {
+            if !is_float && encoding == ColourEncoding::Srgb {
+                decode_srgb(*pixel)
+            } else {
+                *pixel
+            }
+        };
+        let colour_encoding = description.encoding.unwrap_or(ColourEncoding::Srgb);
+        let value_encoding = description.encoding.unwrap_or(ColourEncoding::Linear);
+        let pixels: Vec<_> = channels
+            .iter()
+            .map(|pixel| decode(pixel, colour_encoding))
+            .collect();
+        let average = pixels.iter().copied().sum::<Colour>() / pixels.len() as f32;
+        let values = channels
+            .iter()
+            .map(|pixel| {
+                let pixel = decode(pixel, value_encoding);
+                (pixel.r + pixel.g + pixel.b) / 3.0
+            })
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+            values,
+            average,
+            description,
+        })
+    }
+
+    /// Index of the pixel at `x`, `y`, wrapped onto the image.
+    fn index(&self, x: i64, y: i64) -> usize {
+        let x = self.description.wrap.apply(x, self.width);
+        let y = self.description.wrap.apply(y, self.height);
+        y * self.width + x
+    }
+
+    /// Bilinear filtering at `uv` of whatever `texel` looks up at each pixel index.
+    fn filter<T, F, L>(&self, uv: Vector2<f32>, texel: F, lerp: L) -> T
+    where
+        F: Fn(usize) -> T,
+        L: Fn(T, T, f32) -> T,
+    {
+        // Pixel centres lie half way between whole pixel coordinates
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+        let (left, top) = (x.floor(), y.floor());
+        let (fraction_x, fraction_y) = (x - left, y - top);
+        let (left, top) = (left as i64, top as i64);
+
+        let upper = lerp(
+            texel(self.index(left, top)),
+            texel(self.index(left + 1, top)),
+            fraction_x,
+        );
+        let lower = lerp(
+            texel(self.index(left, top + 1)),
+            texel(self.index(left + 1, top + 1)),
+            fraction_x,
+        );
+        lerp(upper, lower, fraction_y)
+    }
+
+    /// Bilinearly filtered colour at `uv`.
+    pub fn sample(&self, uv: Vector2<f32>) -> Colour {
+        self.filter(uv, |index| self.pixels[index], Colour::lerp)
+    }
+
+    /// Bilinearly filtered scalar value at `uv`.
+    pub fn sample_value(&self, uv: Vector2<f32>) -> f32 {
+        self.filter(uv, |index| self.values[index], |a, b, t| a + (b - a) * t)
+    }
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImageTexture")
+            .field("description", &self.description)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl TryFrom<ImageTextureDescription> for ImageTexture {
+    type Error = String;
+
+    fn try_from(description: ImageTextureDescription) -> Result<Self, Self::Error> {
+        ImageTexture::load(description)
+    }
+}
+
+impl Serialize for ImageTexture {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.description.serialize(serializer)
+    }
+}
+
+#[typetag::serde]
+impl Texture for ImageTexture {
+    fn colour(&self, hit: &Hit) -> Colour {
+        self.sample(hit.uv)
+    }
+
+    fn value(&self, hit: &Hit) -> f32 {
+        self.sample_value(hit.uv)
+    }
+
+    fn average(&self) -> Colour {
+        self.average
+    }
+}
+
+#[test]
+pub fn sample_image_texture() {
+    use crate::scene::TestDirectory;
+    use assert_approx_eq::assert_approx_eq;
+    use image::{Rgb, RgbImage};
+
+    // Black on the left, white on the right
+    let directory = TestDirectory::new("texture");
+    let file_name = directory.join("texture.png");
+    RgbImage::from_fn(2, 2, |x, _| Rgb([255 * x as u8; 3]))
+        .save(&file_name)
+        .unwrap();
+
+    let colour: ColourSource = serde_json::from_str(&format!(
+        "{{ \"ImageTexture\": {{ \"file_name\": {:?}, \"wrap\": \"Clamp\" }} }}",
+        file_name.to_str().unwrap()
+    ))
+    .unwrap();
+    let ColourSource::Texture(texture) = &colour else {
+        panic!("expected a texture, got {:?}", colour);
+    };
+    let texture = serde_json::to_string(texture).unwrap();
+    assert!(texture.contains("\"wrap\":\"Clamp\""));
+
+    let load = |encoding| {
+        ImageTexture::load(ImageTextureDescription {
+            file_name: file_name.to_str().unwrap().to_string(),
+            wrap: WrapMode::Clamp,
+            encoding,
+        })
+        .unwrap()
+    };
+    let texture = load(None);
+    assert_eq!(texture.sample(Vector2::new(0.25, 0.5)).r, 0.0);
+    assert_approx_eq!(texture.sample(Vector2::new(0.5, 0.5)).r, 0.5, 1e-6);
+    assert_eq!(texture.sample(Vector2::new(2.0, 0.5)).r, 1.0);
+
+    // Grey pixels are decoded from sRGB as colours, but taken as they are for scalar maps
+    // unless the texture says otherwise
+    RgbImage::from_pixel(1, 1, Rgb([128; 3]))
+        .save(&file_name)
+        .unwrap();
+    let grey = 128.0 / 255.0;
+    let texture = load(None);
+    assert_approx_eq!(
+        texture.sample(Vector2::new(0.5, 0.5)).g,
+        srgb_to_linear(grey),
+        1e-6
+    );
+    assert_approx_eq!(texture.sample_value(Vector2::new(0.5, 0.5)), grey, 1e-6);
+    let texture = load(Some(ColourEncoding::Linear));
+    assert_approx_eq!(texture.sample(Vector2::new(0.5, 0.5)).g, grey, 1e-6);
+    let texture = load(Some(ColourEncoding::Srgb));
+    assert_approx_eq!(
+        texture.sample_value(Vector2::new(0.5, 0.5)),
+        srgb_to_linear(grey),
+        1e-6
+    );
+
+    // Coordinates off the image wrap around or reflect back onto it
+    assert_eq!(WrapMode::Repeat.apply(-1, 2), 1);
+    assert_eq!(WrapMode::Mirror.apply(-1, 2), 0);
+    assert_eq!(WrapMode::Mirror.apply(2, 2), 1);
+
+    // Plain colours still parse as constants
+    let constant: ColourSource =
+        serde_json::from_str("{ \"r\": 0.5, \"g\": 0.5, \"b\": 0.5, \"a\": 1 }").unwrap();
+    assert!(matches!(constant, ColourSource::Constant(_)));
+    let constant: ScalarSource = serde_json::from_str("0.25").unwrap();
+    assert!(matches!(constant, ScalarSource::Constant(value) if value == 0.25));
+
+    // Errors in textures are reported rather than lost trying each kind of source in turn
+    let error = serde_json::from_str::<ColourSource>(
+        "{ \"ImageTexture\": { \"file_name\": \"does-not-exist.png\" } }",
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(
+        error.contains("failed to load texture 'does-not-exist.png'"),
+        "{}",
+        error
+    );
+    let error = serde_json::from_str::<ScalarSource>(&format!(
+        "{{ \"ImageTexture\": {{ \"file_name\": {:?}, \"wrap\": \"Tile\" }} }}",
+        file_name.to_str().unwrap()
+    ))
+    .unwrap_err()
+    .to_string();
+    assert!(error.contains("unknown variant `Tile`"), "{}", error);
+}
@@ -0,0 +1,168 @@
+use crate::colour::Colour;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Operator compressing linear HDR values into the displayable [0, 1] range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum ToneMappingOperator {
+    /// Values above one are clipped
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on luminance; never quite reaches white
+    Reinhard,
+    /// Reinhard, with luminances at or above the white point mapped to white
+    ExtendedReinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+/// Turns linear HDR render output into sRGB encoded colours in [0, 1], ready for quantisation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapper {
+    pub operator: ToneMappingOperator,
+    /// Exposure adjustment in stops, applied before the operator
+    pub exposure: f32,
+    /// Smallest luminance mapped to white by `ExtendedReinhard`
+    pub white_point: f32,
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        Self {
+            operator: ToneMappingOperator::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+        }
+    }
+}
+
+impl ToneMapper {
+    pub fn apply(&self, colour: Colour) -> Colour {
+        let exposed = colour * self.exposure.exp2();
+        let mapped = match self.operator {
+            ToneMappingOperator::Clamp => exposed,
+            ToneMappingOperator::Reinhard => scale_luminance(exposed, |l| l / (1.0 + l)),
+            ToneMappingOperator::ExtendedReinhard => {
+                let white_point_squared = self.white_point * self.white_point;
+                scale_luminance(exposed, |l| l * (1.0 + l / white_point_squared) / (1.0 + l))
+            }
+            ToneMappingOperator::Aces => Colour {
+                r: aces_filmic(exposed.r),
+                g: aces_filmic(exposed.g),
+                b: aces_filmic(exposed.b),
+                a: exposed.a,
+            },
+        };
+
+        Colour {
+            r: linear_to_srgb(mapped.r.clamp(0.0, 1.0)),
+            g: linear_to_srgb(mapped.g.clamp(0.0, 1.0)),
+            b: linear_to_srgb(mapped.b.clamp(0.0, 1.0)),
+            a: colour.a.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn apply_to_image(&self, image: &[Colour]) -> Vec<Colour> {
+        image.iter().map(|pixel| self.apply(*pixel)).collect()
+    }
+}
+
+/// Rec. 709 relative luminance of a linear colour.
+pub fn luminance(colour: &Colour) -> f32 {
+    0.2126 * colour.r + 0.7152 * colour.g + 0.0722 * colour.b
+}
+
+/// Maps the luminance of the colour and scales its channels to match, preserving hue.
+fn scale_luminance<F>(colour: Colour, map: F) -> Colour
+where
+    F: Fn(f32) -> f32,
+{
+    let luminance = luminance(&colour);
+    if luminance <= 0.0 {
+        return colour;
+    }
+
+    let scale = map(luminance) / luminance;
+    Colour {
+        r: colour.r * scale,
+        g: colour.g * scale,
+        b: colour.b * scale,
+        a: colour.a,
+    }
+}
+
+fn aces_filmic(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// The sRGB transfer function ("gamma"), for a linear value in [0, 1].
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Inverse of `linear_to_srgb`.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl FromStr for ToneMappingOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clamp" => Ok(ToneMappingOperator::Clamp),
+            "reinhard" => Ok(ToneMappingOperator::Reinhard),
+            "extended-reinhard" => Ok(ToneMappingOperator::ExtendedReinhard),
+            "aces" => Ok(ToneMappingOperator::Aces),
+            _ => Err(format!(
+                "unknown tone mapping operator '{}', expected clamp, reinhard, extended-reinhard or aces",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ToneMappingOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ToneMappingOperator::Clamp => "clamp",
+            ToneMappingOperator::Reinhard => "reinhard",
+            ToneMappingOperator::ExtendedReinhard => "extended-reinhard",
+            ToneMappingOperator::Aces => "aces",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[test]
+pub fn tone_map_colours() {
+    use crate::colour::{BLACK, WHITE};
+    use assert_approx_eq::assert_approx_eq;
+
+    assert_approx_eq!(linear_to_srgb(0.5), 0.735357, 1e-5);
+    assert_approx_eq!(srgb_to_linear(linear_to_srgb(0.2)), 0.2, 1e-6);
+
+    let clamp = ToneMapper::default();
+    assert_approx_eq!(clamp.apply(WHITE * 4.0).r, 1.0, 1e-6);
+    assert_eq!(clamp.apply(BLACK).r, 0.0);
+
+    let extended_reinhard = ToneMapper {
+        operator: ToneMappingOperator::ExtendedReinhard,
+        ..Default::default()
+    };
+    assert_approx_eq!(extended_reinhard.apply(WHITE * 4.0).g, 1.0, 1e-5);
+
+    let exposed = ToneMapper {
+        exposure: -1.0,
+        ..Default::default()
+    };
+    assert_approx_eq!(exposed.apply(WHITE).b, linear_to_srgb(0.5), 1e-6);
+}
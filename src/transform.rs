@@ -0,0 +1,377 @@
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    hit::Hit,
+    intersectable::{orthonormal_basis, Intersectable, Intersectables, Interval},
+    light::{Emitter, SurfaceSample},
+    material::Material,
+    ray::Ray,
+};
+use cgmath::{
+    Deg, Euler, InnerSpace, Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix, Transform as _,
+    Vector3,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{collections::HashMap, sync::Arc};
+
+/// A rotation, given either as angles in degrees about the x, y and z axes or as a quaternion.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum Rotation {
+    Euler(Euler<Deg<f32>>),
+    Quaternion(Quaternion<f32>),
+}
+
+impl From<Rotation> for Matrix4<f32> {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Euler(euler) => Quaternion::from(euler).into(),
+            Rotation::Quaternion(quaternion) => quaternion.normalize().into(),
+        }
+    }
+}
+
+/// How a transform is described in the scene file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TransformDescription {
+    #[serde(default = "default_translation")]
+    pub translation: Vector3<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Rotation>,
+    #[serde(default = "default_scale")]
+    pub scale: Vector3<f32>,
+    /// Row-major matrix, applied to the child before scale, rotation and translation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix: Option<[[f32; 4]; 4]>,
+    pub child: Box<dyn Intersectable>,
+}
+
+fn default_translation() -> Vector3<f32> {
+    Vector3::new(0.0, 0.0, 0.0)
+}
+
+fn default_scale() -> Vector3<f32> {
+    Vector3::new(1.0, 1.0, 1.0)
+}
+
+impl TransformDescription {
+    /// Matrix taking the child's object space to the space the transform is placed in.
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        let rotation = self.rotation.map_or_else(Matrix4::identity, Matrix4::from);
+        let matrix = self
+            .matrix
+            .map_or_else(Matrix4::identity, |rows| Matrix4::from(rows).transpose());
+
+        Matrix4::from_translation(self.translation)
+            * rotation
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+            * matrix
+    }
+}
+
+/// Places its child in the scene by translating, rotating and scaling it. Rays are taken into the
+/// child's object space to be intersected, and the hits brought back out.
+///
+/// To place the same model several times without copying it, make the child a `Reference` to one
+/// of the scene's definitions.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "TransformDescription")]
+pub struct Transform {
+    description: TransformDescription,
+    affine: Affine,
+}
+
+impl Transform {
+    /// Creates a transform. A child holding several intersectables is given its own hierarchy,
+    /// since the scene's does not reach inside transforms.
+    pub fn new(mut description: TransformDescription) -> Result<Self, String> {
+        let affine = Affine::new(description.to_matrix())
+            .ok_or_else(|| "transform matrix is not invertible".to_string())?;
+        if let Some(children) = description.child.take_children() {
+            description.child = Box::new(Bvh::from_root(Box::new(Intersectables {
+                intersectables: children,
+            })));
+        }
+        Ok(Self {
+            description,
+            affine,
+        })
+    }
+}
+
+impl TryFrom<TransformDescription> for Transform {
+    type Error = String;
+
+    fn try_from(description: TransformDescription) -> Result<Self, Self::Error> {
+        Transform::new(description)
+    }
+}
+
+impl Serialize for Transform {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.description.serialize(serializer)
+    }
+}
+
+#[typetag::serde]
+impl Intersectable for Transform {
+    fn intersect(&self, ray: &Ray) -> Option<Hit> {
+        let (object_ray, scale) = self.affine.ray_to_object(ray);
+        let hit = self.description.child.intersect(&object_ray)?;
+        Some(self.affine.hit_to_world(ray, hit, scale))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Option<Vec<Interval>> {
+        let (object_ray, scale) = self.affine.ray_to_object(ray);
+        let intervals = self.description.child.intervals(&object_ray)?;
+        Some(
+            intervals
+                .into_iter()
+                .map(|interval| Interval {
+                    entry: self.affine.hit_to_world(ray, interval.entry, scale),
+                    exit: self.affine.hit_to_world(ray, interval.exit, scale),
+                })
+                .collect(),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bounds = self.description.child.bounding_box()?;
+        Some(Aabb::from_points(
+            bounds
+                .corners()
+                .map(|corner| self.affine.to_world.transform_point(corner)),
+        ))
+    }
+
+    fn collect_emitters(&self, emitters: &mut Vec<Arc<dyn Emitter>>) {
+        let mut child_emitters = Vec::new();
+        self.description.child.collect_emitters(&mut child_emitters);
+        emitters.extend(child_emitters.into_iter().map(|emitter| {
+            Arc::new(TransformedEmitter {
+                emitter,
+                affine: self.affine,
+            }) as Arc<dyn Emitter>
+        }));
+    }
+
+    fn resolve_references(
+        &mut self,
+        definitions: &HashMap<String, Arc<dyn Intersectable>>,
+    ) -> Result<(), String> {
+        self.description.child.resolve_references(definitions)
+    }
+}
+
+/// An affine transform along with the inverses needed to move rays, points and normals between
+/// object and world space.
+#[derive(Debug, Clone, Copy)]
+struct Affine {
+    to_world: Matrix4<f32>,
+    to_object: Matrix4<f32>,
+    /// Inverse transpose of the linear part, taking object space normals to world space
+    normal_to_world: Matrix3<f32>,
+    /// Transpose of the linear part, taking world space normals to object space
+    normal_to_object: Matrix3<f32>,
+    /// Factor by which volumes grow going into world space
+    determinant: f32,
+}
+
+impl Affine {
+    fn new(to_world: Matrix4<f32>) -> Option<Self> {
+        let linear = Matrix3::from_cols(
+            to_world.x.truncate(),
+            to_world.y.truncate(),
+            to_world.z.truncate(),
+        );
+
+        Some(Self {
+            to_world,
+            to_object: to_world.invert()?,
+            normal_to_world: linear.invert()?.transpose(),
+            normal_to_object: linear.transpose(),
+            determinant: linear.determinant().abs(),
+        })
+    }
+
+    /// `ray` in object space, along with the object space length of a unit of world distance.
+    fn ray_to_object(&self, ray: &Ray) -> (Ray, f32) {
+        let direction = self.to_object.transform_vector(ray.direction);
+        let origin = self.to_object.transform_point(ray.origin);
+        (Ray::new(origin, direction), direction.magnitude())
+    }
+
+    /// Brings a hit on the object space version of `ray` back into world space.
+    fn hit_to_world(&self, ray: &Ray, hit: Hit, scale: f32) -> Hit {
+        let distance = hit.distance / scale;
+        let normal = (self.normal_to_world * hit.normal).normalize();
+        let geometric_normal = (self.normal_to_world * hit.geometric_normal).normalize();
+        let tangent = self.to_world.transform_vector(hit.tangent);
+        Hit {
+            distance,
+            position: ray.origin + ray.direction * distance,
+            normal,
+            geometric_normal,
+            tangent: orthonormal_basis(normal, None).0,
+            ..hit
+        }
+        .with_tangent(tangent)
+    }
+
+    /// Factor by which an area with unit normal `normal` in object space grows in world space.
+    fn area_scale(&self, normal: Vector3<f32>) -> f32 {
+        self.determinant * (self.normal_to_world * normal).magnitude()
+    }
+}
+
+/// An emitter below a `Transform`, sampled in its own object space.
+#[derive(Debug)]
+struct TransformedEmitter {
+    emitter: Arc<dyn Emitter>,
+    affine: Affine,
+}
+
+impl Emitter for TransformedEmitter {
+    fn sample_surface(&self) -> SurfaceSample {
+        let sample = self.emitter.sample_surface();
+        SurfaceSample {
+            position: self.affine.to_world.transform_point(sample.position),
+            normal: (self.affine.normal_to_world * sample.normal).normalize(),
+            pdf: sample.pdf / self.affine.area_scale(sample.normal),
+        }
+    }
+
+    fn surface_pdf(&self, hit: &Hit) -> f32 {
+        let object_hit = Hit {
+            position: self.affine.to_object.transform_point(hit.position),
+            normal: (self.affine.normal_to_object * hit.normal).normalize(),
+            geometric_normal: (self.affine.normal_to_object * hit.geometric_normal).normalize(),
+            ..hit.clone()
+        };
+        self.emitter.surface_pdf(&object_hit) / self.affine.area_scale(object_hit.normal)
+    }
+
+    fn intersect_surface(&self, ray: &Ray) -> Option<Hit> {
+        let (object_ray, scale) = self.affine.ray_to_object(ray);
+        let hit = self.emitter.intersect_surface(&object_ray)?;
+        Some(self.affine.hit_to_world(ray, hit, scale))
+    }
+
+    fn area(&self) -> f32 {
+        // Exact for rotations and uniform scales, and the area under the uniform scale with the
+        // same change of volume otherwise
+        self.emitter.area() * self.affine.determinant.powf(2.0 / 3.0)
+    }
+
+    fn material(&self) -> &Arc<dyn Material> {
+        self.emitter.material()
+    }
+}
+
+#[test]
+pub fn intersect_transformed_sphere() {
+    use crate::{colour, material::LightMaterial, sphere::Sphere};
+    use assert_approx_eq::assert_approx_eq;
+    use cgmath::Point3;
+
+    // A unit sphere stretched to an ellipsoid, then moved up
+    let transform: Box<dyn Intersectable> = serde_json::from_str(
+        "{ \"Transform\": {
+            \"translation\": { \"x\": 0, \"y\": 2, \"z\": 0 },
+            \"rotation\": { \"Euler\": { \"x\": 0, \"y\": 90, \"z\": 0 } },
+            \"scale\": { \"x\": 3, \"y\": 1, \"z\": 1 },
+            \"child\": { \"Sphere\": {
+                \"centre\": { \"x\": 0, \"y\": 0, \"z\": 0 },
+                \"radius\": 1,
+                \"material\": { \"LightMaterial\": { \"colour\": { \"r\": 1, \"g\": 1, \"b\": 1, \"a\": 1 } } }
+            } }
+        } }",
+    )
+    .unwrap();
+
+    // Rotated about y, the long axis of the ellipsoid points along z
+    let hit = transform
+        .intersect(&Ray::new(
+            Point3::new(0.0, 2.0, 10.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        ))
+        .unwrap();
+    assert_approx_eq!(hit.distance, 7.0, 1e-4);
+    assert_approx_eq!(hit.position.y, 2.0, 1e-4);
+    assert_approx_eq!(hit.normal.z, 1.0, 1e-4);
+
+    // However the sphere is stretched and sheared, the tangent stays perpendicular to the normal
+    let sheared = Affine::new(
+        Matrix4::from_nonuniform_scale(3.0, 0.5, 1.0)
+            * Matrix4::from(Matrix3::new(1.0, 0.0, 0.0, 0.8, 1.0, 0.0, 0.0, 0.3, 1.0)),
+    )
+    .unwrap();
+    let sphere = Sphere {
+        centre: Point3::new(0.0, 0.0, 0.0),
+        radius: 1.0,
+        material: Arc::new(LightMaterial {
+            colour: colour::WHITE.into(),
+        }),
+    };
+    let ray = Ray::new(Point3::new(1.0, 0.4, 10.0), Vector3::new(0.0, 0.0, -1.0));
+    let (object_ray, scale) = sheared.ray_to_object(&ray);
+    let hit = sheared.hit_to_world(&ray, sphere.intersect(&object_ray).unwrap(), scale);
+    assert_approx_eq!(cgmath::dot(hit.tangent, hit.normal), 0.0, 1e-6);
+    assert_approx_eq!(hit.tangent.magnitude(), 1.0, 1e-6);
+
+    let bounds = transform.bounding_box().unwrap();
+    assert_approx_eq!(bounds.max.z, 3.0, 1e-4);
+    assert_approx_eq!(bounds.min.y, 1.0, 1e-4);
+
+    // The emitter's area grows with the transform
+    let mut emitters = Vec::new();
+    transform.collect_emitters(&mut emitters);
+    let sphere_area = 4.0 * std::f32::consts::PI;
+    let scaled = TransformedEmitter {
+        emitter: Arc::new(Sphere {
+            centre: Point3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: Arc::new(LightMaterial {
+                colour: colour::WHITE.into(),
+            }),
+        }),
+        affine: Affine::new(Matrix4::from_scale(2.0)).unwrap(),
+    };
+    assert_approx_eq!(scaled.sample_surface().pdf, 1.0 / (4.0 * sphere_area), 1e-6);
+    assert_eq!(emitters.len(), 1);
+}
+
+#[test]
+pub fn build_hierarchy_under_transform() {
+    use cgmath::Point3;
+
+    let sphere = |x: i32| {
+        format!(
+            "{{ \"Sphere\": {{
+                \"centre\": {{ \"x\": {}, \"y\": 0, \"z\": 0 }},
+                \"radius\": 0.5,
+                \"material\": {{ \"LightMaterial\": {{ \"colour\": {{ \"r\": 1, \"g\": 1, \"b\": 1, \"a\": 1 }} }} }}
+            }} }}",
+            x
+        )
+    };
+    let transform: Box<dyn Intersectable> = serde_json::from_str(&format!(
+        "{{ \"Transform\": {{
+            \"translation\": {{ \"x\": 0, \"y\": 1, \"z\": 0 }},
+            \"child\": {{ \"Intersectables\": {{ \"intersectables\": [{}] }} }}
+        }} }}",
+        (-5..5).map(sphere).collect::<Vec<_>>().join(", ")
+    ))
+    .unwrap();
+    assert!(serde_json::to_string(&transform)
+        .unwrap()
+        .contains("\"Bvh\""));
+
+    let hit = transform
+        .intersect(&Ray::new(
+            Point3::new(3.0, 1.0, 10.0),
+            Vector3::new(0.0, 0.0, -1.0),
+        ))
+        .unwrap();
+    assert_eq!(hit.distance, 9.5);
+    assert_eq!(transform.bounding_box().unwrap().max.x, 4.5);
+}
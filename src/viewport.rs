@@ -1,12 +1,26 @@
-use crate::ray::Ray;
+use crate::{
+    camera::{Lens, Projection},
+    filter::FilterSampler,
+    ray::Ray,
+};
 use cgmath::{Basis3, Matrix3, Point3};
+use rand::Rng;
+use std::f32::consts::PI;
 
 #[derive(Debug, Clone)]
 pub struct Viewport {
     width: f32,
     height: f32,
+    /// Camera axes, with x running to the right of the image, y down it and z forward
+    axes: Matrix3<f32>,
+    /// Extent of the image along the image axes, one unit in front of a perspective camera
     basis: Matrix3<f32>,
     origin: Point3<f32>,
+    fov: f32,
+    lens: Lens,
+    projection: Projection,
+    /// Width of the view of an orthographic projection
+    orthographic_width: f32,
     current_x: f32,
     current_y: f32,
 }
@@ -18,38 +32,156 @@ impl Viewport {
         basis: Basis3<f32>,
         origin: Point3<f32>,
         fov: f32,
+        lens: Lens,
+        projection: Projection,
     ) -> Self {
         let aspect_ratio = height as f32 / width as f32;
         let delta_x = (fov / 2.0).tan() * 2.0;
         let delta_y = delta_x * aspect_ratio;
-        let mut basis = basis.as_ref().clone();
-        basis.x = basis.x * delta_x;
-        basis.y = basis.y * delta_y;
+        // Image x runs to the right of the camera and image y downwards
+        let mut axes = *basis.as_ref();
+        axes.x = -axes.x;
+        axes.y = -axes.y;
+        let mut basis = axes;
+        basis.x *= delta_x;
+        basis.y *= delta_y;
 
         Self {
             width: width as f32,
             height: height as f32,
+            axes,
             basis,
             origin,
+            fov,
+            lens,
+            projection,
+            orthographic_width: delta_x * lens.focus_distance,
             current_x: 0.0,
             current_y: 0.0,
         }
     }
+
+    /// Sets the width of the view of an orthographic projection, which is otherwise as wide as
+    /// the perspective view at the focus distance.
+    pub fn with_orthographic_width(self, orthographic_width: f32) -> Self {
+        Self {
+            orthographic_width,
+            ..self
+        }
+    }
+
+    /// Ray through the point (`x`, `y`) on the image, measured in pixels from its corner, or
+    /// `None` if the projection shows nothing there.
+    pub fn ray(&self, x: f32, y: f32) -> Option<Ray> {
+        let x = (x / self.width) - 0.5;
+        let y = (y / self.height) - 0.5;
+        let aspect_ratio = self.height / self.width;
+
+        match self.projection {
+            Projection::Perspective => Some(self.perspective_ray(x, y)),
+            Projection::Orthographic => {
+                let offset = self.axes.x * x + self.axes.y * (y * aspect_ratio);
+                Some(Ray::new(
+                    self.origin + offset * self.orthographic_width,
+                    self.axes.z,
+                ))
+            }
+            Projection::Fisheye => {
+                // The angle from the view direction grows in step with the distance from the
+                // centre of the image, reaching half the field of view at its sides
+                let (angle_x, angle_y) = (x * self.fov, y * self.fov * aspect_ratio);
+                let angle = (angle_x * angle_x + angle_y * angle_y).sqrt();
+                if angle > PI {
+                    return None;
+                }
+                if angle == 0.0 {
+                    return Some(Ray::new(self.origin, self.axes.z));
+                }
+
+                let sideways = (self.axes.x * angle_x + self.axes.y * angle_y) / angle;
+                Some(Ray::new(
+                    self.origin,
+                    sideways * angle.sin() + self.axes.z * angle.cos(),
+                ))
+            }
+            Projection::Equirectangular => {
+                // Longitude all the way around across the image, and latitude from straight up
+                // at the top to straight down at the bottom
+                let (longitude, latitude) = (2.0 * PI * x, -PI * y);
+                let direction = self.axes.x * (latitude.cos() * longitude.sin())
+                    + self.axes.z * (latitude.cos() * longitude.cos())
+                    - self.axes.y * latitude.sin();
+                Some(Ray::new(self.origin, direction))
+            }
+        }
+    }
+
+    fn perspective_ray(&self, x: f32, y: f32) -> Ray {
+        let direction = self.basis.z + (x * self.basis.x) + (y * self.basis.y);
+        if self.lens.is_pinhole() {
+            return Ray::new(self.origin, direction);
+        }
+
+        // The direction reaches one unit along the view direction, so scaling it by the focus
+        // distance gives the point in focus; rays from all over the lens converge there.
+        let focus_point = self.origin + direction * self.lens.focus_distance;
+        let (lens_x, lens_y) = self.lens.sample_aperture(&mut rand::thread_rng());
+        let lens_point = self.origin + self.axes.x * lens_x + self.axes.y * lens_y;
+        Ray::new(lens_point, focus_point - lens_point)
+    }
+
+    /// Jittered rays through the pixel at (`x`, `y`), placed by `filter` and paired with the
+    /// weight it gives them.
+    pub fn jittered_rays<'a>(
+        &'a self,
+        x: usize,
+        y: usize,
+        samples_per_pixel: usize,
+        filter: &'a FilterSampler,
+    ) -> impl Iterator<Item = (Option<Ray>, f32)> + 'a {
+        stratified_offsets(samples_per_pixel).map(move |(u, v)| {
+            let (offset_x, offset_y, weight) = filter.sample(u + 0.5, v + 0.5);
+            let ray = self.ray(x as f32 + 0.5 + offset_x, y as f32 + 0.5 + offset_y);
+            (ray, weight)
+        })
+    }
 }
 
+/// Offsets in [-0.5, 0.5) from the centre of a unit square, each placed randomly within its own
+/// stratum of a grid over the square.
+///
+/// The grid is always filled, so every part of the square is sampled evenly: it has as many rows
+/// as the largest factor of `samples` no greater than its square root, which is a single row when
+/// the number of samples is prime.
+fn stratified_offsets(samples: usize) -> impl Iterator<Item = (f32, f32)> {
+    let strata_y = (1..=(samples as f32).sqrt() as usize)
+        .rev()
+        .find(|&strata| samples.is_multiple_of(strata))
+        .unwrap_or(1);
+    let strata_x = samples.max(1) / strata_y;
+    let mut rng = rand::thread_rng();
+
+    (0..samples).map(move |sample| {
+        let stratum_x = (sample % strata_x) as f32;
+        let stratum_y = (sample / strata_x) as f32;
+        (
+            (stratum_x + rng.gen::<f32>()) / strata_x as f32 - 0.5,
+            (stratum_y + rng.gen::<f32>()) / strata_y as f32 - 0.5,
+        )
+    })
+}
+
+/// The ray through the corner of each pixel in turn, row by row, or `None` for pixels the
+/// projection shows nothing at, so that every pixel keeps its place.
 impl Iterator for Viewport {
-    type Item = Ray;
+    type Item = Option<Ray>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.current_y >= self.height {
             return None;
         }
 
-        let x = (self.current_x / self.width) - 0.5;
-        let y = (self.current_y / self.height) - 0.5;
-
-        let direction = self.basis.z + (x * self.basis.x) + (y * self.basis.y);
-        let next_ray = Ray::new(self.origin, direction);
+        let next_ray = self.ray(self.current_x, self.current_y);
 
         self.current_x += 1.0;
 
@@ -61,3 +193,26 @@ impl Iterator for Viewport {
         Some(next_ray)
     }
 }
+
+#[test]
+pub fn stratify_samples_evenly() {
+    for samples in [1, 2, 3, 4, 6, 7, 9, 16] {
+        let mut sum_x = 0.0;
+        let mut sum_y = 0.0;
+        let trials = 10000 / samples;
+        for _ in 0..trials {
+            let offsets: Vec<_> = stratified_offsets(samples).collect();
+            assert_eq!(offsets.len(), samples);
+            for (x, y) in offsets {
+                assert!((-0.5..0.5).contains(&x) && (-0.5..0.5).contains(&y));
+                sum_x += x;
+                sum_y += y;
+            }
+        }
+
+        // Uniform offsets average to zero with a standard error of about 0.003
+        let count = (trials * samples) as f32;
+        assert!((sum_x / count).abs() < 0.02, "{} samples", samples);
+        assert!((sum_y / count).abs() < 0.02, "{} samples", samples);
+    }
+}